@@ -0,0 +1,18 @@
+//! 
+//! List all audio devices available via PortAudio.
+//!
+
+extern crate sound_stream;
+
+fn main() {
+
+    let devices = sound_stream::devices().unwrap();
+
+    for device in devices.iter() {
+        println!("{}: {} ({})", device.idx, device.name, device.host_api);
+        println!("    max input channels: {}", device.max_input_channels);
+        println!("    max output channels: {}", device.max_output_channels);
+        println!("    default sample rate: {}", device.default_sample_hz);
+    }
+
+}
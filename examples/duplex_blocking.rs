@@ -26,10 +26,13 @@ fn main() {
     // We'll use this to copy the input buffer straight to the output buffer.
     let mut intermediate = Vec::new();
 
-    for event in stream.by_ref() {
+    while let Some(event) = stream.next_event() {
         match event {
-            Event::In(input, _) => { ::std::mem::replace(&mut intermediate, input); }
-            Event::Out(output, settings) => {
+            Event::In(input, _, _) => {
+                intermediate.clear();
+                intermediate.extend_from_slice(input);
+            },
+            Event::Out(output, settings, _) => {
                 for (output_sample, sample) in output.iter_mut().zip(intermediate.iter()) {
                     *output_sample = *sample;
                 }
@@ -6,7 +6,8 @@
 
 extern crate sound_stream;
 
-use sound_stream::{CallbackFlags, CallbackResult, SoundStream, Settings, StreamParams};
+use sound_stream::{Buffer, BufferMut, CallbackFlags, CallbackResult, SoundStream, Settings,
+                   StreamParams, StreamTime};
 
 fn main() {
 
@@ -14,11 +15,12 @@ fn main() {
     let mut count = 3.0;
 
     // The callback we'll use to pass to the Stream. It will write the input directly to the output.
-    let f = Box::new(move |i: &[f32], _: Settings, o: &mut[f32], _: Settings, dt: f64, _: CallbackFlags| {
+    let f = Box::new(move |i: Buffer<f32>, _: Settings, mut o: BufferMut<f32>, _: Settings,
+                           time: StreamTime, _: CallbackFlags| {
         for (output_sample, input_sample) in o.iter_mut().zip(i.iter()) {
             *output_sample = *input_sample;
         }
-        count -= dt;
+        count -= time.dt;
         if count >= 0.0 { CallbackResult::Continue } else { CallbackResult::Complete }
     });
 
@@ -0,0 +1,47 @@
+//! 
+//! Render a 440hz sine wave with the Offline backend, without touching any audio hardware.
+//!
+//! This is how streams may be exercised on headless machines (i.e. CI servers).
+//!
+
+extern crate sound_stream;
+
+use sound_stream::{BufferMut, CallbackFlags, CallbackResult, Offline, SoundStream, Settings,
+                   StreamParams, StreamTime};
+
+fn main() {
+
+    // We'll keep a clone of the backend so that we can drive the clock and inspect the output.
+    let offline = Offline::new().sample_hz(44_100.0).channels(0, 1);
+
+    // We'll use this as the phase for our oscillator.
+    let mut phase = 0.0;
+
+    // The callback we'll use to pass to the Stream. It will write a 440hz sine wave to the output.
+    let callback = Box::new(move |mut output: BufferMut<f32>, settings: Settings, _: StreamTime,
+                                  _: CallbackFlags| {
+        for sample in output.iter_mut() {
+            *sample = (phase * ::std::f64::consts::PI * 2.0).sin() as f32;
+            phase += 440.0 / settings.exact_sample_hz;
+        }
+        CallbackResult::Continue
+    });
+
+    let stream = SoundStream::new()
+        .backend(offline.clone())
+        .frames_per_buffer(64)
+        .output(StreamParams::new())
+        .run_callback(callback)
+        .unwrap();
+
+    // Process one second of audio, as fast as possible.
+    offline.process(44_100);
+
+    let output = offline.take_output::<f32>();
+    assert_eq!(output.len(), 44_100 / 64 * 64);
+    assert!(stream.is_active().unwrap());
+
+    let peak = output.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    println!("Rendered {} frames with a peak amplitude of {}", output.len(), peak);
+
+}
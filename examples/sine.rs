@@ -4,7 +4,8 @@
 
 extern crate sound_stream;
 
-use sound_stream::{CallbackFlags, CallbackResult, SoundStream, Settings, StreamParams};
+use sound_stream::{BufferMut, CallbackFlags, CallbackResult, SoundStream, Settings, StreamParams,
+                   StreamTime};
 
 /// Produce a sine wave given some phase.
 fn sine_wave(phase: f64) -> f32 {
@@ -20,15 +21,16 @@ fn main() {
     let mut phase = 0.0;
 
     // The callback we'll use to pass to the Stream. It will write a 440hz sine wave to the output.
-    let callback = Box::new(move |output: &mut[f32], settings: Settings, dt: f64, _: CallbackFlags| {
-        for frame in output.chunks_mut(settings.channels as usize) {
+    let callback = Box::new(move |mut output: BufferMut<f32>, settings: Settings, time: StreamTime,
+                                  _: CallbackFlags| {
+        for frame in output.frames_mut() {
             let amp = sine_wave(phase);
             for channel in frame {
                 *channel = amp;
             }
-            phase += 440.0 / settings.sample_hz as f64;
+            phase += 440.0 / settings.exact_sample_hz;
         }
-        count -= dt;
+        count -= time.dt;
         if count >= 0.0 { CallbackResult::Continue } else { CallbackResult::Complete }
     });
 
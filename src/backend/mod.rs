@@ -0,0 +1,167 @@
+//! 
+//! The Backend trait through which the stream builders talk to the host audio API.
+//!
+//! PortAudio is the default implementation, however any type implementing `Backend` may be
+//! handed to `SoundStream::backend` (i.e. an alternative host or a test double).
+//!
+
+use device::DeviceInfo;
+use error::Error;
+use portaudio::pa;
+use portaudio::pa::Sample as PaSample;
+use sample::Sample;
+use stream::{CallbackFlags, CallbackResult, Idx, StreamFlags};
+
+pub use self::offline::Offline;
+pub use self::portaudio::PortAudio;
+
+mod offline;
+mod portaudio;
+
+/// The callback that a `Backend` calls for every buffer of a non-blocking stream.
+///
+/// Arguments are the input buffer, the output buffer, the number of frames in each buffer, the
+/// stream's timing information and any flags describing the state of the buffers.
+pub type RawCallback<I, O> =
+    Box<FnMut(&[I], &mut [O], u32, &pa::StreamCallbackTimeInfo, CallbackFlags) -> CallbackResult>;
+
+/// An audio host capable of describing its devices and opening streams on them.
+pub trait Backend {
+
+    /// Prepare the backend for use. Called by the builders before any other method.
+    ///
+    /// Backends must tolerate multiple calls, as each `Host` initializes the backend once.
+    fn initialize(&self) -> Result<(), Error>;
+
+    /// Release the backend. Called once for every successful call to `initialize`, so
+    /// backends should only tear down when the last caller has terminated.
+    fn terminate(&self) -> Result<(), Error>;
+
+    /// The number of devices available.
+    fn device_count(&self) -> Result<Idx, Error>;
+
+    /// The index of the default input device.
+    fn default_input_device(&self) -> Result<Idx, Error>;
+
+    /// The index of the default output device.
+    fn default_output_device(&self) -> Result<Idx, Error>;
+
+    /// Information about the device at the given index.
+    fn device_info(&self, idx: Idx) -> Result<DeviceInfo, Error>;
+
+    /// Information about every device available.
+    fn devices(&self) -> Result<Vec<DeviceInfo>, Error> {
+        let count = self.device_count()?;
+        (0..count).map(|idx| self.device_info(idx)).collect()
+    }
+
+    /// Check whether a stream could be opened with the given parameters, without opening it.
+    fn is_format_supported(&self,
+                           input: Option<&pa::StreamParameters>,
+                           output: Option<&pa::StreamParameters>,
+                           sample_hz: f64) -> Result<(), Error>;
+
+    /// Open a stream with the given parameters.
+    ///
+    /// If a `callback` is given the stream is non-blocking and the backend must call it for every
+    /// buffer once the stream is started. Otherwise the stream is blocking and is driven via the
+    /// `read` and `write` methods of the returned `RawStream`.
+    fn open_stream<I, O>(&self,
+                         input: Option<&pa::StreamParameters>,
+                         output: Option<&pa::StreamParameters>,
+                         sample_hz: f64,
+                         frames: u32,
+                         flags: StreamFlags,
+                         callback: Option<RawCallback<I, O>>) -> Result<Box<RawStream<I, O>>, Error>
+        where
+            I: Sample + PaSample + 'static,
+            O: Sample + PaSample + 'static;
+
+}
+
+/// A handle that keeps a `Backend` initialized for as long as it lives.
+///
+/// Every stream owns a `Host` and the backend is terminated once the last `Host` drops, meaning
+/// any number of streams may run concurrently and closing one won't tear down the backend under
+/// the others. Holding a `Host` also keeps the backend initialized between streams.
+pub struct Host<B=PortAudio> where B: Backend {
+    backend: B,
+    is_released: bool,
+}
+
+impl<B> Host<B> where B: Backend {
+
+    /// Initialize the given backend, returning a handle that will terminate it on drop.
+    pub fn new(backend: B) -> Result<Host<B>, Error> {
+        backend.initialize()?;
+        Ok(Host::initialized(backend))
+    }
+
+    /// Wrap a backend that has already been initialized.
+    pub fn initialized(backend: B) -> Host<B> {
+        Host { backend, is_released: false }
+    }
+
+    /// The backend kept alive by the handle.
+    #[inline]
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Terminate the backend without waiting for the handle to drop.
+    pub fn release(&mut self) -> Result<(), Error> {
+        if self.is_released {
+            return Ok(());
+        }
+        self.is_released = true;
+        self.backend.terminate()
+    }
+
+}
+
+impl<B> Drop for Host<B> where B: Backend {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+/// A stream that has been opened by a `Backend`.
+pub trait RawStream<I, O> {
+
+    /// Start processing audio.
+    fn start(&mut self) -> Result<(), Error>;
+
+    /// Stop processing audio once all pending buffers have been played.
+    fn stop(&mut self) -> Result<(), Error>;
+
+    /// Stop processing audio immediately, discarding any pending buffers.
+    fn abort(&mut self) -> Result<(), Error>;
+
+    /// Close the stream, releasing the device.
+    fn close(&mut self) -> Result<(), Error>;
+
+    /// Whether or not the stream is currently processing audio.
+    fn is_active(&self) -> Result<bool, Error>;
+
+    /// Whether or not the stream has been stopped (or was never started).
+    fn is_stopped(&self) -> Result<bool, Error>;
+
+    /// The sample rate at which the stream is running, which may differ slightly from the one
+    /// requested when it was opened.
+    fn sample_hz(&self) -> f64;
+
+    /// The number of frames that may be read without blocking.
+    fn read_available(&self) -> Result<pa::StreamAvailable, Error>;
+
+    /// The number of frames that may be written without blocking.
+    fn write_available(&self) -> Result<pa::StreamAvailable, Error>;
+
+    /// Read the given number of frames from a blocking stream into the given buffer, which must
+    /// hold exactly `frames * channels` samples.
+    fn read(&mut self, buffer: &mut [I], frames: u32) -> Result<(), Error>;
+
+    /// Write the given number of frames to a blocking stream from the given buffer, which must
+    /// hold exactly `frames * channels` samples.
+    fn write(&mut self, buffer: &[O], frames: u32) -> Result<(), Error>;
+
+}
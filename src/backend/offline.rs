@@ -0,0 +1,711 @@
+//!
+//! A deterministic, offline Backend for running streams without any audio hardware.
+//!
+
+use device::DeviceInfo;
+use error::Error;
+use portaudio::pa;
+use portaudio::pa::Sample as PaSample;
+use sample::{Sample, Wave};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use stream::{CallbackFlags, CallbackResult, Idx, StreamFlags};
+
+use super::{Backend, RawCallback, RawStream};
+
+/// The index of the virtual input device.
+const INPUT_DEVICE: Idx = 0;
+/// The index of the virtual output device.
+const OUTPUT_DEVICE: Idx = 1;
+
+/// A virtual backend that runs streams against a simulated clock.
+///
+/// Nothing happens in real time. Non-blocking streams opened on an `Offline` backend are only
+/// driven when `Offline::process` is called, at which point the clock is advanced and each active
+/// stream's callback is called for every buffer that fits within the elapsed frames. Input is
+/// read from samples scripted with `push_input` (silence once exhausted) and all output is
+/// captured so that it may be retrieved with `take_output`.
+///
+/// Clones of an `Offline` share the same clock, input script and captured output, so a clone may
+/// be kept for driving the backend after handing the original to `SoundStream::backend`.
+#[derive(Clone)]
+pub struct Offline {
+    data: Rc<RefCell<Data>>,
+    entries: Rc<RefCell<Vec<Entry>>>,
+}
+
+/// The clock, devices and buffers shared between an `Offline` backend and its streams.
+struct Data {
+    /// The sample rate of the virtual devices.
+    sample_hz: f64,
+    /// The frames per buffer used for streams that request a variable buffer size.
+    frames: u32,
+    /// Number of channels on the virtual input device.
+    in_channels: i32,
+    /// Number of channels on the virtual output device.
+    out_channels: i32,
+    /// The position of the simulated clock in frames.
+    clock: u64,
+    /// Scripted input samples that have not yet been read.
+    input: VecDeque<Wave>,
+    /// Output samples that have been captured from the streams.
+    output: Vec<Wave>,
+    /// The id to assign to the next non-blocking stream.
+    next_id: usize,
+    /// The name, input channels and output channels of each device added with `add_device`.
+    extra_devices: Vec<(String, i32, i32)>,
+    /// The number of input overflows still to be reported to blocking streams.
+    input_overflows: usize,
+    /// The number of output underflows still to be reported to blocking streams.
+    output_underflows: usize,
+}
+
+/// A non-blocking stream registered with the backend.
+struct Entry {
+    id: usize,
+    /// Frames per buffer.
+    frames: u32,
+    /// The stream's sample rate.
+    sample_hz: f64,
+    /// The number of frames processed by the stream so far.
+    position: u64,
+    /// Whether or not the stream is currently processing.
+    is_active: bool,
+    /// Pulls input from, calls the user's callback and pushes output to the shared `Data`.
+    process: Process,
+}
+
+/// Processes a single buffer of a non-blocking stream.
+type Process = Box<FnMut(&mut Data, u32, &pa::StreamCallbackTimeInfo) -> CallbackResult>;
+
+/// A stream opened by the Offline backend.
+pub struct Stream<I, O> {
+    offline: Offline,
+    /// The id of the stream's `Entry` if it is non-blocking.
+    maybe_id: Option<usize>,
+    /// Frames per buffer.
+    frames: u32,
+    /// The configured sample rate, which every stream must use.
+    sample_hz: f64,
+    in_channels: usize,
+    out_channels: usize,
+    /// Whether or not a blocking stream has been started.
+    is_active: bool,
+    /// Whether or not the stream has been stopped (or was never started).
+    is_stopped: bool,
+    /// Whether or not the stream has been closed.
+    is_closed: bool,
+    marker: PhantomData<(I, O)>,
+}
+
+impl Offline {
+
+    /// Construct an Offline backend with a stereo input and output device at 44.1khz.
+    pub fn new() -> Offline {
+        let data = Data {
+            sample_hz: 44_100.0,
+            frames: 256,
+            in_channels: 2,
+            out_channels: 2,
+            clock: 0,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            next_id: 0,
+            extra_devices: Vec::new(),
+            input_overflows: 0,
+            output_underflows: 0,
+        };
+        Offline {
+            data: Rc::new(RefCell::new(data)),
+            entries: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// The sample rate of the virtual devices.
+    pub fn sample_hz(self, sample_hz: f64) -> Offline {
+        self.data.borrow_mut().sample_hz = sample_hz;
+        self
+    }
+
+    /// The number of channels on the virtual input and output devices.
+    pub fn channels(self, input: i32, output: i32) -> Offline {
+        {
+            let mut data = self.data.borrow_mut();
+            data.in_channels = input;
+            data.out_channels = output;
+        }
+        self
+    }
+
+    /// The frames per buffer used for streams that request a variable buffer size.
+    ///
+    /// **Panics** if `frames` is `0`.
+    pub fn default_frames(self, frames: u32) -> Offline {
+        assert!(frames > 0, "the default frames per buffer must be greater than 0");
+        self.data.borrow_mut().frames = frames;
+        self
+    }
+
+    /// Add a virtual device with the given name and channels, listed after the default input and
+    /// output devices.
+    ///
+    /// Streams can't be opened on added devices. They exist to test how devices are selected.
+    pub fn add_device(self, name: &str, in_channels: i32, out_channels: i32) -> Offline {
+        self.data.borrow_mut().extra_devices.push((name.to_string(), in_channels, out_channels));
+        self
+    }
+
+    /// Append interleaved samples to the input script.
+    pub fn push_input<S>(&self, samples: &[S]) where S: Sample {
+        self.data.borrow_mut().input.extend(samples.iter().map(|&sample| sample.to_wave()));
+    }
+
+    /// Take all interleaved output samples captured so far.
+    ///
+    /// The output of all streams is captured in the order in which it was produced.
+    pub fn take_output<S>(&self) -> Vec<S> where S: Sample {
+        let output = ::std::mem::take(&mut self.data.borrow_mut().output);
+        output.into_iter().map(|wave| S::from_wave(wave)).collect()
+    }
+
+    /// Have the next check for available input on a blocking stream report an input overflow,
+    /// as PortAudio does when input was discarded before it could be read.
+    pub fn overflow_input(&self) {
+        self.data.borrow_mut().input_overflows += 1;
+    }
+
+    /// Have the next check for available output space on a blocking stream report an output
+    /// underflow, as PortAudio does when the device ran out of samples to play.
+    pub fn underflow_output(&self) {
+        self.data.borrow_mut().output_underflows += 1;
+    }
+
+    /// The position of the simulated clock in frames.
+    pub fn clock(&self) -> u64 {
+        self.data.borrow().clock
+    }
+
+    /// Advance the simulated clock by the given number of frames.
+    ///
+    /// Each active non-blocking stream has its callback called for every whole buffer that fits
+    /// within the new clock position. Streams whose callback returns `Complete` or `Abort` become
+    /// inactive.
+    ///
+    /// **Panics** if called from within a stream's callback.
+    pub fn process(&self, frames: u64) {
+        let mut data = self.data.borrow_mut();
+        let mut entries = self.entries.borrow_mut();
+        data.clock += frames;
+        for entry in entries.iter_mut() {
+            while entry.is_active && entry.position + entry.frames as u64 <= data.clock {
+                let current_time = entry.position as f64 / entry.sample_hz;
+                let time_info = pa::StreamCallbackTimeInfo {
+                    input_buffer_adc_time: current_time,
+                    current_time,
+                    output_buffer_dac_time: current_time + entry.frames as f64 / entry.sample_hz,
+                };
+                let result = (entry.process)(&mut data, entry.frames, &time_info);
+                entry.position += entry.frames as u64;
+                if result != CallbackResult::Continue {
+                    entry.is_active = false;
+                }
+            }
+        }
+    }
+
+}
+
+impl Default for Offline {
+    fn default() -> Offline {
+        Offline::new()
+    }
+}
+
+impl PartialEq for Offline {
+    fn eq(&self, other: &Offline) -> bool {
+        ::std::ptr::eq(&*self.data, &*other.data)
+    }
+}
+
+impl Data {
+
+    /// Fill the given buffer from the input script, padding with silence.
+    fn take_input<S>(&mut self, buffer: &mut [S]) where S: Sample {
+        let available = ::std::cmp::min(buffer.len(), self.input.len());
+        for (sample, wave) in buffer.iter_mut().zip(self.input.drain(..available)) {
+            *sample = S::from_wave(wave);
+        }
+        for sample in buffer[available..].iter_mut() {
+            *sample = S::zero();
+        }
+    }
+
+}
+
+impl Backend for Offline {
+
+    fn initialize(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn terminate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn device_count(&self) -> Result<Idx, Error> {
+        Ok(2 + self.data.borrow().extra_devices.len() as Idx)
+    }
+
+    fn default_input_device(&self) -> Result<Idx, Error> {
+        Ok(INPUT_DEVICE)
+    }
+
+    fn default_output_device(&self) -> Result<Idx, Error> {
+        Ok(OUTPUT_DEVICE)
+    }
+
+    fn device_info(&self, idx: Idx) -> Result<DeviceInfo, Error> {
+        let data = self.data.borrow();
+        let (name, max_input_channels, max_output_channels) = match idx {
+            INPUT_DEVICE => ("Offline Input", data.in_channels, 0),
+            OUTPUT_DEVICE => ("Offline Output", 0, data.out_channels),
+            _ => match data.extra_devices.get((idx - 2) as usize) {
+                Some(&(ref name, in_channels, out_channels)) if idx > OUTPUT_DEVICE =>
+                    (&name[..], in_channels, out_channels),
+                _ => return Err(Error::DeviceNotFound(format!("device with index {}", idx))),
+            },
+        };
+        let latency = data.frames as f64 / data.sample_hz;
+        Ok(DeviceInfo {
+            idx,
+            name: name.to_string(),
+            host_api: "Offline".to_string(),
+            max_input_channels,
+            max_output_channels,
+            default_low_input_latency: latency,
+            default_low_output_latency: latency,
+            default_high_input_latency: latency,
+            default_high_output_latency: latency,
+            default_sample_hz: data.sample_hz,
+        })
+    }
+
+    fn is_format_supported(&self,
+                           input: Option<&pa::StreamParameters>,
+                           output: Option<&pa::StreamParameters>,
+                           sample_hz: f64) -> Result<(), Error>
+    {
+        let data = self.data.borrow();
+        if let Some(input) = input {
+            if input.device != INPUT_DEVICE {
+                return Err(Error::PortAudio(pa::Error::InvalidDevice));
+            }
+            if input.channel_count > data.in_channels {
+                return Err(Error::PortAudio(pa::Error::InvalidChannelCount));
+            }
+        }
+        if let Some(output) = output {
+            if output.device != OUTPUT_DEVICE {
+                return Err(Error::PortAudio(pa::Error::InvalidDevice));
+            }
+            if output.channel_count > data.out_channels {
+                return Err(Error::PortAudio(pa::Error::InvalidChannelCount));
+            }
+        }
+        // The virtual devices only run at their configured rate.
+        if sample_hz != data.sample_hz {
+            return Err(Error::PortAudio(pa::Error::InvalidSampleRate));
+        }
+        Ok(())
+    }
+
+    fn open_stream<I, O>(&self,
+                         input: Option<&pa::StreamParameters>,
+                         output: Option<&pa::StreamParameters>,
+                         sample_hz: f64,
+                         frames: u32,
+                         _flags: StreamFlags,
+                         callback: Option<RawCallback<I, O>>) -> Result<Box<RawStream<I, O>>, Error>
+        where
+            I: Sample + PaSample + 'static,
+            O: Sample + PaSample + 'static,
+    {
+        self.is_format_supported(input, output, sample_hz)?;
+
+        let frames = if frames == 0 { self.data.borrow().frames } else { frames };
+        let in_channels = input.map(|params| params.channel_count as usize).unwrap_or(0);
+        let out_channels = output.map(|params| params.channel_count as usize).unwrap_or(0);
+
+        let maybe_id = callback.map(|mut callback| {
+            // Buffers are reused between calls to avoid allocating for every buffer.
+            let mut input_buffer: Vec<I> = Vec::new();
+            let mut output_buffer: Vec<O> = Vec::new();
+            let process = Box::new(move |data: &mut Data,
+                                         frames: u32,
+                                         time_info: &pa::StreamCallbackTimeInfo| {
+                input_buffer.resize(frames as usize * in_channels, I::zero());
+                data.take_input(&mut input_buffer);
+                output_buffer.clear();
+                output_buffer.resize(frames as usize * out_channels, O::zero());
+                let result = callback(&input_buffer, &mut output_buffer, frames, time_info,
+                                      CallbackFlags::empty());
+                data.output.extend(output_buffer.iter().map(|&sample| sample.to_wave()));
+                result
+            });
+            let mut data = self.data.borrow_mut();
+            let id = data.next_id;
+            data.next_id += 1;
+            self.entries.borrow_mut().push(Entry {
+                id,
+                frames,
+                sample_hz,
+                position: 0,
+                is_active: false,
+                process,
+            });
+            id
+        });
+
+        Ok(Box::new(Stream {
+            offline: self.clone(),
+            maybe_id,
+            frames,
+            sample_hz,
+            in_channels,
+            out_channels,
+            is_active: false,
+            is_stopped: true,
+            is_closed: false,
+            marker: PhantomData,
+        }))
+    }
+
+}
+
+impl<I, O> Stream<I, O> {
+
+    /// Apply the given function to the stream's `Entry` if it has one.
+    fn with_entry<F, T>(&self, f: F) -> Option<T> where F: FnOnce(&mut Entry) -> T {
+        let mut entries = self.offline.entries.borrow_mut();
+        self.maybe_id.and_then(|id| entries.iter_mut().find(|entry| entry.id == id)).map(f)
+    }
+
+}
+
+impl<I, O> RawStream<I, O> for Stream<I, O>
+    where
+        I: Sample + PaSample,
+        O: Sample + PaSample,
+{
+
+    fn start(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::PortAudio(pa::Error::BadStreamPtr));
+        }
+        let clock = self.offline.clock();
+        self.with_entry(|entry| {
+            entry.position = clock;
+            entry.is_active = true;
+        });
+        self.is_active = true;
+        self.is_stopped = false;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        // There are never any pending buffers as the clock only advances via `process`.
+        self.abort()
+    }
+
+    fn abort(&mut self) -> Result<(), Error> {
+        self.with_entry(|entry| entry.is_active = false);
+        self.is_active = false;
+        self.is_stopped = true;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        // Like PortAudio, the callback is only released once the stream is dropped.
+        self.with_entry(|entry| entry.is_active = false);
+        self.is_closed = true;
+        self.is_active = false;
+        self.is_stopped = true;
+        Ok(())
+    }
+
+    fn is_active(&self) -> Result<bool, Error> {
+        Ok(self.with_entry(|entry| entry.is_active).unwrap_or(self.is_active))
+    }
+
+    fn is_stopped(&self) -> Result<bool, Error> {
+        Ok(self.is_stopped)
+    }
+
+    fn sample_hz(&self) -> f64 {
+        self.sample_hz
+    }
+
+    fn read_available(&self) -> Result<pa::StreamAvailable, Error> {
+        let mut data = self.offline.data.borrow_mut();
+        if data.input_overflows > 0 {
+            data.input_overflows -= 1;
+            return Ok(pa::StreamAvailable::InputOverflowed);
+        }
+        Ok(pa::StreamAvailable::Frames(self.frames as i64))
+    }
+
+    fn write_available(&self) -> Result<pa::StreamAvailable, Error> {
+        let mut data = self.offline.data.borrow_mut();
+        if data.output_underflows > 0 {
+            data.output_underflows -= 1;
+            return Ok(pa::StreamAvailable::OutputUnderflowed);
+        }
+        Ok(pa::StreamAvailable::Frames(self.frames as i64))
+    }
+
+    fn read(&mut self, buffer: &mut [I], frames: u32) -> Result<(), Error> {
+        let len = frames as usize * self.in_channels;
+        let mut data = self.offline.data.borrow_mut();
+        data.take_input(&mut buffer[..len]);
+        data.clock += frames as u64;
+        Ok(())
+    }
+
+    fn write(&mut self, buffer: &[O], frames: u32) -> Result<(), Error> {
+        let len = frames as usize * self.out_channels;
+        let mut data = self.offline.data.borrow_mut();
+        data.output.extend(buffer[..len].iter().map(|&sample| sample.to_wave()));
+        // Only advance the clock for output-only streams so that duplex streams don't count
+        // their frames twice.
+        if self.in_channels == 0 {
+            data.clock += frames as u64;
+        }
+        Ok(())
+    }
+
+}
+
+impl<I, O> Drop for Stream<I, O> {
+    fn drop(&mut self) {
+        if let Some(id) = self.maybe_id {
+            self.offline.entries.borrow_mut().retain(|entry| entry.id != id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use {Buffer, BufferMut, CallbackFlags, CallbackResult, Offline, PlanarMut, Settings,
+         SoundStream, StreamParams, StreamTime};
+
+    #[test]
+    fn output_callback_is_captured() {
+        let offline = Offline::new().channels(0, 2);
+        let mut next = 0.0;
+        let callback = Box::new(move |mut output: BufferMut<f32>, _: Settings, _: StreamTime,
+                                      _: CallbackFlags| {
+            for frame in output.frames_mut() {
+                for sample in frame.iter_mut() {
+                    *sample = next;
+                }
+                next += 1.0;
+            }
+            CallbackResult::Continue
+        });
+        let _stream = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(4)
+            .output(StreamParams::new())
+            .run_callback(callback)
+            .unwrap();
+
+        // Only whole buffers are processed.
+        offline.process(10);
+        let output = offline.take_output::<f32>();
+        assert_eq!(output, vec![0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0,
+                                4.0, 4.0, 5.0, 5.0, 6.0, 6.0, 7.0, 7.0]);
+        offline.process(2);
+        assert_eq!(offline.take_output::<f32>().len(), 4 * 2);
+    }
+
+    #[test]
+    fn pushed_input_reaches_callback() {
+        let offline = Offline::new().channels(1, 0);
+        let received = Rc::new(::std::cell::RefCell::new(Vec::new()));
+        let callback_received = received.clone();
+        let callback = Box::new(move |input: Buffer<f32>, _: Settings, _: StreamTime,
+                                      _: CallbackFlags| {
+            callback_received.borrow_mut().extend(input.iter().cloned());
+            CallbackResult::Continue
+        });
+        let _stream = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(2)
+            .input(StreamParams::new())
+            .run_callback(callback)
+            .unwrap();
+
+        offline.push_input(&[0.25f32, 0.5, 0.75]);
+        offline.process(4);
+        // Silence follows once the scripted input is exhausted.
+        assert_eq!(*received.borrow(), vec![0.25, 0.5, 0.75, 0.0]);
+    }
+
+    fn run_until(offline: &Offline, result: CallbackResult) {
+        let calls = Rc::new(Cell::new(0));
+        let callback_calls = calls.clone();
+        let callback = Box::new(move |_: BufferMut<f32>, _: Settings, _: StreamTime,
+                                      _: CallbackFlags| {
+            callback_calls.set(callback_calls.get() + 1);
+            result
+        });
+        let stream = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(4)
+            .output(StreamParams::new())
+            .run_callback(callback)
+            .unwrap();
+
+        offline.process(16);
+        assert_eq!(calls.get(), 1);
+        assert!(!stream.is_active().unwrap());
+    }
+
+    #[test]
+    fn complete_deactivates_stream() {
+        run_until(&Offline::new(), CallbackResult::Complete);
+    }
+
+    #[test]
+    fn abort_deactivates_stream() {
+        run_until(&Offline::new(), CallbackResult::Abort);
+    }
+
+    #[test]
+    fn start_stop_and_close() {
+        let offline = Offline::new().channels(0, 1);
+        let calls = Rc::new(Cell::new(0));
+        let callback_calls = calls.clone();
+        let callback = Box::new(move |_: BufferMut<f32>, _: Settings, _: StreamTime,
+                                      _: CallbackFlags| {
+            callback_calls.set(callback_calls.get() + 1);
+            CallbackResult::Continue
+        });
+        let mut stream = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(4)
+            .output(StreamParams::new())
+            .run_callback(callback)
+            .unwrap();
+
+        offline.process(4);
+        assert_eq!(calls.get(), 1);
+
+        stream.stop().unwrap();
+        assert!(stream.is_stopped().unwrap());
+        assert!(!stream.is_active().unwrap());
+        offline.process(8);
+        assert_eq!(calls.get(), 1);
+
+        // A restarted stream picks up from the current clock position.
+        stream.start().unwrap();
+        assert!(!stream.is_stopped().unwrap());
+        offline.process(4);
+        assert_eq!(calls.get(), 2);
+
+        // The callback is kept alive until the stream is dropped, as with PortAudio.
+        stream.close().unwrap();
+        assert!(stream.is_active().is_err());
+        offline.process(8);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(Rc::strong_count(&calls), 2);
+        drop(stream);
+        assert_eq!(Rc::strong_count(&calls), 1);
+    }
+
+    #[test]
+    fn read_frames_reads_what_is_available() {
+        let offline = Offline::new().channels(1, 0);
+        let mut stream = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(4)
+            .input(StreamParams::<f32>::new())
+            .run()
+            .unwrap();
+
+        offline.push_input(&[1.0f32, 2.0, 3.0, 4.0, 5.0]);
+        offline.overflow_input();
+        let mut buffer = [0.0; 3];
+        assert_eq!(stream.read_frames(&mut buffer).unwrap(), 3);
+        assert_eq!(buffer, [1.0, 2.0, 3.0]);
+        assert_eq!(stream.xrun_stats().input_overflows, 1);
+
+        // The frame left over from the last read is returned without reading again.
+        let mut buffer = [0.0; 8];
+        assert_eq!(stream.read_frames(&mut buffer).unwrap(), 1);
+        assert_eq!(buffer[0], 4.0);
+        assert_eq!(stream.read_frames(&mut []).unwrap(), 0);
+        assert_eq!(stream.frames_processed(), 4);
+    }
+
+    #[test]
+    fn write_frames_writes_what_the_stream_accepts() {
+        let offline = Offline::new().channels(0, 1);
+        let mut stream = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(4)
+            .output(StreamParams::<f32>::new())
+            .run()
+            .unwrap();
+
+        offline.underflow_output();
+        assert_eq!(stream.write_frames(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap(), 4);
+        assert_eq!(stream.xrun_stats().output_underflows, 1);
+        assert_eq!(stream.write_frames(&[5.0, 6.0]).unwrap(), 2);
+        assert_eq!(stream.write_frames(&[]).unwrap(), 0);
+        assert_eq!(offline.take_output::<f32>(), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(stream.frames_processed(), 6);
+    }
+
+    #[test]
+    fn large_variable_buffers_are_chunked_for_planar_callbacks() {
+        let offline = Offline::new().channels(0, 2).default_frames(5000);
+        let chunks = Rc::new(::std::cell::RefCell::new(Vec::new()));
+        let callback_chunks = chunks.clone();
+        let callback = Box::new(move |mut output: PlanarMut<f32>, settings: Settings,
+                                      time: StreamTime, _: CallbackFlags| {
+            callback_chunks.borrow_mut().push((settings.frames, time.frame));
+            for (i, sample) in output.channel_mut(1).iter_mut().enumerate() {
+                *sample = (time.frame as usize + i) as f32;
+            }
+            match callback_chunks.borrow().len() {
+                3 => CallbackResult::Complete,
+                _ => CallbackResult::Continue,
+            }
+        });
+        let _stream = SoundStream::new()
+            .backend(offline.clone())
+            .output(StreamParams::new())
+            .run_planar_callback(callback)
+            .unwrap();
+
+        offline.process(5000);
+        assert_eq!(*chunks.borrow(), vec![(2048, 0), (2048, 2048), (904, 4096)]);
+        let output = offline.take_output::<f32>();
+        assert_eq!(output.len(), 5000 * 2);
+        for (i, frame) in output.chunks(2).enumerate() {
+            assert_eq!(frame, &[0.0, i as f32][..]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_default_frames_is_rejected() {
+        Offline::new().default_frames(0);
+    }
+
+}
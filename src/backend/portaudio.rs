@@ -0,0 +1,272 @@
+//! 
+//! The default PortAudio implementation of the Backend trait.
+//!
+
+use device::DeviceInfo;
+use error::Error;
+use num::FromPrimitive;
+use portaudio::pa;
+use portaudio::pa::Sample as PaSample;
+use sample::Sample;
+use std::os::raw::{c_int, c_ulong, c_void};
+use std::sync::Mutex;
+use stream::{CallbackFlags, CallbackResult, Idx, StreamFlags};
+
+use super::{Backend, RawCallback, RawStream};
+
+/// The PortAudio host backend.
+///
+/// PortAudio is initialized process-wide by the first call to `initialize` and only terminated
+/// once every call has been matched by a call to `terminate`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PortAudio;
+
+/// The number of outstanding `initialize` calls on the PortAudio backend.
+static HOST_REFERENCES: Mutex<usize> = Mutex::new(0);
+
+/// Functions that rust-portaudio does not expose in a form we can use.
+mod ffi {
+    use std::os::raw::{c_int, c_ulong, c_void};
+
+    extern "C" {
+        pub fn Pa_IsStreamStopped(stream: *mut c_void) -> c_int;
+        pub fn Pa_ReadStream(stream: *mut c_void, buffer: *mut c_void, frames: c_ulong) -> c_int;
+        pub fn Pa_WriteStream(stream: *mut c_void, buffer: *const c_void, frames: c_ulong)
+            -> c_int;
+    }
+}
+
+/// A stream opened by the PortAudio backend.
+pub struct Stream<I: PaSample, O: PaSample> {
+    stream: pa::Stream<I, O>,
+    /// The frames per buffer the stream was opened with, or `0` if variable.
+    frames: u32,
+    in_channels: usize,
+    out_channels: usize,
+}
+
+impl Backend for PortAudio {
+
+    fn initialize(&self) -> Result<(), Error> {
+        let mut references = HOST_REFERENCES.lock().unwrap_or_else(|err| err.into_inner());
+        if *references == 0 {
+            pa::initialize().map_err(|err| Error::BackendUnavailable(err.to_string()))?;
+        }
+        *references += 1;
+        Ok(())
+    }
+
+    fn terminate(&self) -> Result<(), Error> {
+        let mut references = HOST_REFERENCES.lock().unwrap_or_else(|err| err.into_inner());
+        if *references == 0 {
+            return Ok(());
+        }
+        *references -= 1;
+        if *references == 0 {
+            pa::terminate().map_err(Error::from)?;
+        }
+        Ok(())
+    }
+
+    fn device_count(&self) -> Result<Idx, Error> {
+        // PortAudio reports errors as a negative device count.
+        let count = pa::device::get_count();
+        if count < 0 {
+            return Err(error_from_code(count));
+        }
+        Ok(count)
+    }
+
+    fn default_input_device(&self) -> Result<Idx, Error> {
+        default_device(pa::device::get_default_input(), "default input device")
+    }
+
+    fn default_output_device(&self) -> Result<Idx, Error> {
+        default_device(pa::device::get_default_output(), "default output device")
+    }
+
+    fn device_info(&self, idx: Idx) -> Result<DeviceInfo, Error> {
+        let info = pa::device::get_info(idx).map_err(Error::from)?;
+        let host_api = pa::host::get_api_info(info.host_api)
+            .map(|host_api| host_api.name)
+            .unwrap_or_default();
+        Ok(DeviceInfo {
+            idx,
+            name: info.name,
+            host_api,
+            max_input_channels: info.max_input_channels,
+            max_output_channels: info.max_output_channels,
+            default_low_input_latency: info.default_low_input_latency,
+            default_low_output_latency: info.default_low_output_latency,
+            default_high_input_latency: info.default_high_input_latency,
+            default_high_output_latency: info.default_high_output_latency,
+            default_sample_hz: info.default_sample_rate,
+        })
+    }
+
+    fn is_format_supported(&self,
+                           input: Option<&pa::StreamParameters>,
+                           output: Option<&pa::StreamParameters>,
+                           sample_hz: f64) -> Result<(), Error>
+    {
+        pa::is_format_supported(input, output, sample_hz).map_err(Error::from)
+    }
+
+    fn open_stream<I, O>(&self,
+                         input: Option<&pa::StreamParameters>,
+                         output: Option<&pa::StreamParameters>,
+                         sample_hz: f64,
+                         frames: u32,
+                         flags: StreamFlags,
+                         callback: Option<RawCallback<I, O>>) -> Result<Box<RawStream<I, O>>, Error>
+        where
+            I: Sample + PaSample + 'static,
+            O: Sample + PaSample + 'static,
+    {
+        let mut stream = pa::Stream::new();
+
+        match callback {
+            // Wrap the callback so that it may be called by PortAudio.
+            Some(mut callback) => {
+                let f = Box::new(move |input: &[I],
+                                       output: &mut[O],
+                                       frames: u32,
+                                       time_info: &pa::StreamCallbackTimeInfo,
+                                       flags: pa::StreamCallbackFlags| -> pa::StreamCallbackResult {
+                    let flags = CallbackFlags::from(flags);
+                    match callback(input, output, frames, time_info, flags) {
+                        CallbackResult::Continue => pa::StreamCallbackResult::Continue,
+                        CallbackResult::Complete => pa::StreamCallbackResult::Complete,
+                        CallbackResult::Abort    => pa::StreamCallbackResult::Abort,
+                    }
+                });
+                stream.open(input, output, sample_hz, frames, flags, Some(f))
+                    .map_err(Error::from)?;
+            },
+            None => {
+                stream.open(input, output, sample_hz, frames, flags, None)
+                    .map_err(Error::from)?;
+            },
+        }
+
+        Ok(Box::new(Stream {
+            stream,
+            frames,
+            in_channels: input.map(|params| params.channel_count as usize).unwrap_or(0),
+            out_channels: output.map(|params| params.channel_count as usize).unwrap_or(0),
+        }))
+    }
+
+}
+
+/// Check a default device index, which PortAudio reports as `paNoDevice` (`-1`) when there is no
+/// default device.
+fn default_device(idx: Idx, device: &str) -> Result<Idx, Error> {
+    if idx < 0 {
+        return Err(Error::DeviceNotFound(device.to_string()));
+    }
+    Ok(idx)
+}
+
+/// Convert a negative PortAudio error code into an `Error`.
+fn error_from_code(code: c_int) -> Error {
+    Error::from(FromPrimitive::from_i32(code).unwrap_or(pa::Error::InternalError))
+}
+
+impl<I, O> Stream<I, O> where I: PaSample, O: PaSample {
+
+    /// The underlying `PaStream` for calling PortAudio directly.
+    fn c_stream(&self) -> *mut c_void {
+        self.stream.get_c_pa_stream() as *mut c_void
+    }
+
+}
+
+impl<I, O> RawStream<I, O> for Stream<I, O>
+    where
+        I: Sample + PaSample,
+        O: Sample + PaSample,
+{
+
+    fn start(&mut self) -> Result<(), Error> {
+        self.stream.start().map_err(Error::from)
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        self.stream.stop().map_err(Error::from)
+    }
+
+    fn abort(&mut self) -> Result<(), Error> {
+        self.stream.abort().map_err(Error::from)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.stream.close().map_err(Error::from)
+    }
+
+    fn is_active(&self) -> Result<bool, Error> {
+        self.stream.is_active().map_err(Error::from)
+    }
+
+    fn is_stopped(&self) -> Result<bool, Error> {
+        // rust-portaudio declares the result as a `pa::Error`, which has no variant for the 1
+        // returned once the stream is stopped, so we call PortAudio directly.
+        match unsafe { ffi::Pa_IsStreamStopped(self.c_stream()) } {
+            0 => Ok(false),
+            1 => Ok(true),
+            code => Err(error_from_code(code)),
+        }
+    }
+
+    fn sample_hz(&self) -> f64 {
+        self.stream.get_stream_info().sample_rate
+    }
+
+    fn read_available(&self) -> Result<pa::StreamAvailable, Error> {
+        self.stream.get_stream_read_available().map_err(Error::from)
+    }
+
+    fn write_available(&self) -> Result<pa::StreamAvailable, Error> {
+        self.stream.get_stream_write_available().map_err(Error::from)
+    }
+
+    // rust-portaudio's blocking read and write take and return owned `Vec`s, and its read fills
+    // an internal buffer that only holds `frames_per_buffer` frames (none at all when the number
+    // of frames is variable). We call PortAudio directly with the caller's buffers instead, in
+    // chunks of at most the frames per buffer that the stream was opened with.
+
+    fn read(&mut self, buffer: &mut [I], frames: u32) -> Result<(), Error> {
+        let channels = self.in_channels;
+        assert!(buffer.len() >= frames as usize * channels,
+                "the buffer must hold `frames * channels` samples");
+        let chunk_frames = if self.frames == 0 { frames } else { self.frames };
+        let mut read = 0;
+        while read < frames {
+            let chunk = ::std::cmp::min(chunk_frames, frames - read);
+            let ptr = buffer[read as usize * channels..].as_mut_ptr() as *mut c_void;
+            match unsafe { ffi::Pa_ReadStream(self.c_stream(), ptr, chunk as c_ulong) } {
+                0 => read += chunk,
+                code => return Err(error_from_code(code)),
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, buffer: &[O], frames: u32) -> Result<(), Error> {
+        let channels = self.out_channels;
+        assert!(buffer.len() >= frames as usize * channels,
+                "the buffer must hold `frames * channels` samples");
+        let chunk_frames = if self.frames == 0 { frames } else { self.frames };
+        let mut written = 0;
+        while written < frames {
+            let chunk = ::std::cmp::min(chunk_frames, frames - written);
+            let ptr = buffer[written as usize * channels..].as_ptr() as *const c_void;
+            match unsafe { ffi::Pa_WriteStream(self.c_stream(), ptr, chunk as c_ulong) } {
+                0 => written += chunk,
+                code => return Err(error_from_code(code)),
+            }
+        }
+        Ok(())
+    }
+
+}
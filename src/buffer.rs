@@ -0,0 +1,293 @@
+//!
+//! Frame-aware views over the interleaved buffers passed to non-blocking stream callbacks.
+//!
+//! Both views dereference to the underlying slice of samples, so they may be used anywhere a
+//! slice was used previously.
+//!
+
+use std::ops::{Deref, DerefMut};
+use std::slice;
+
+/// An interleaved buffer of samples, passed to input callbacks.
+#[derive(Copy, Clone, Debug)]
+pub struct Buffer<'a, S: 'a> {
+    samples: &'a [S],
+    channels: usize,
+}
+
+/// A mutable interleaved buffer of samples, passed to output callbacks.
+#[derive(Debug)]
+pub struct BufferMut<'a, S: 'a> {
+    samples: &'a mut [S],
+    channels: usize,
+}
+
+/// An iterator yielding every sample of a single channel.
+pub struct Channel<'a, S: 'a> {
+    iter: slice::Iter<'a, S>,
+    channels: usize,
+}
+
+/// An iterator yielding every mutable sample of a single channel.
+pub struct ChannelMut<'a, S: 'a> {
+    iter: slice::IterMut<'a, S>,
+    channels: usize,
+}
+
+/// A fixed-size frame of samples, one per channel, i.e. `[f32; 2]` for stereo.
+///
+/// # Safety
+///
+/// A slice of samples is reinterpreted as a slice of frames, so the implementing type must have
+/// the same layout as `channels()` consecutive samples.
+pub unsafe trait Frame: Copy {
+    /// The type of sample held for each channel.
+    type Sample;
+    /// The number of channels in the frame.
+    fn channels() -> usize;
+}
+
+macro_rules! impl_frame {
+    ($($n:expr)*) => {
+        $(
+            unsafe impl<S> Frame for [S; $n] where S: Copy {
+                type Sample = S;
+                fn channels() -> usize { $n }
+            }
+        )*
+    };
+}
+
+impl_frame!(1 2 3 4 5 6 7 8);
+
+impl<'a, S> Buffer<'a, S> {
+
+    /// View the given interleaved samples as a buffer with the given number of channels.
+    ///
+    /// **Panics** if `channels` is `0` or does not evenly divide the number of samples.
+    pub fn new(samples: &'a [S], channels: usize) -> Buffer<'a, S> {
+        assert!(channels > 0 && samples.len() % channels == 0,
+                "the number of samples must be a multiple of the number of channels");
+        Buffer { samples, channels }
+    }
+
+    /// The number of channels.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The number of frames in the buffer.
+    pub fn len_frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// An iterator yielding each frame as a slice with one sample per channel.
+    pub fn frames(&self) -> slice::Chunks<'a, S> {
+        self.samples.chunks(self.channels)
+    }
+
+    /// An iterator yielding the samples of the channel at the given index.
+    ///
+    /// **Panics** if `idx` is out of range.
+    pub fn channel(&self, idx: usize) -> Channel<'a, S> {
+        assert!(idx < self.channels, "channel index out of range");
+        Channel { iter: self.samples[idx..].iter(), channels: self.channels }
+    }
+
+    /// View the buffer as a slice of fixed-size frames without copying.
+    ///
+    /// Returns `None` if the number of channels in `F` differs from that of the buffer.
+    pub fn as_frames<F>(&self) -> Option<&'a [F]> where F: Frame<Sample=S> {
+        if F::channels() != self.channels {
+            return None;
+        }
+        let len = self.len_frames();
+        Some(unsafe { slice::from_raw_parts(self.samples.as_ptr() as *const F, len) })
+    }
+
+}
+
+impl<'a, S> BufferMut<'a, S> {
+
+    /// View the given interleaved samples as a buffer with the given number of channels.
+    ///
+    /// **Panics** if `channels` is `0` or does not evenly divide the number of samples.
+    pub fn new(samples: &'a mut [S], channels: usize) -> BufferMut<'a, S> {
+        assert!(channels > 0 && samples.len() % channels == 0,
+                "the number of samples must be a multiple of the number of channels");
+        BufferMut { samples, channels }
+    }
+
+    /// The number of channels.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The number of frames in the buffer.
+    pub fn len_frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// An iterator yielding each frame as a slice with one sample per channel.
+    pub fn frames<'b>(&'b self) -> slice::Chunks<'b, S> {
+        self.samples.chunks(self.channels)
+    }
+
+    /// An iterator yielding each frame as a mutable slice with one sample per channel.
+    pub fn frames_mut<'b>(&'b mut self) -> slice::ChunksMut<'b, S> {
+        self.samples.chunks_mut(self.channels)
+    }
+
+    /// An iterator yielding the samples of the channel at the given index.
+    ///
+    /// **Panics** if `idx` is out of range.
+    pub fn channel<'b>(&'b self, idx: usize) -> Channel<'b, S> {
+        assert!(idx < self.channels, "channel index out of range");
+        Channel { iter: self.samples[idx..].iter(), channels: self.channels }
+    }
+
+    /// An iterator yielding the mutable samples of the channel at the given index.
+    ///
+    /// **Panics** if `idx` is out of range.
+    pub fn channel_mut<'b>(&'b mut self, idx: usize) -> ChannelMut<'b, S> {
+        assert!(idx < self.channels, "channel index out of range");
+        ChannelMut { iter: self.samples[idx..].iter_mut(), channels: self.channels }
+    }
+
+    /// View the buffer as a slice of fixed-size frames without copying.
+    ///
+    /// Returns `None` if the number of channels in `F` differs from that of the buffer.
+    pub fn as_frames<F>(&self) -> Option<&[F]> where F: Frame<Sample=S> {
+        if F::channels() != self.channels {
+            return None;
+        }
+        let len = self.len_frames();
+        Some(unsafe { slice::from_raw_parts(self.samples.as_ptr() as *const F, len) })
+    }
+
+    /// View the buffer as a mutable slice of fixed-size frames without copying.
+    ///
+    /// Returns `None` if the number of channels in `F` differs from that of the buffer.
+    pub fn as_frames_mut<F>(&mut self) -> Option<&mut [F]> where F: Frame<Sample=S> {
+        if F::channels() != self.channels {
+            return None;
+        }
+        let len = self.len_frames();
+        Some(unsafe { slice::from_raw_parts_mut(self.samples.as_mut_ptr() as *mut F, len) })
+    }
+
+}
+
+impl<'a, S> Deref for Buffer<'a, S> {
+    type Target = [S];
+    fn deref(&self) -> &[S] {
+        self.samples
+    }
+}
+
+impl<'a, S> Deref for BufferMut<'a, S> {
+    type Target = [S];
+    fn deref(&self) -> &[S] {
+        self.samples
+    }
+}
+
+impl<'a, S> DerefMut for BufferMut<'a, S> {
+    fn deref_mut(&mut self) -> &mut [S] {
+        self.samples
+    }
+}
+
+impl<'a, S> Iterator for Channel<'a, S> {
+    type Item = &'a S;
+
+    fn next(&mut self) -> Option<&'a S> {
+        let sample = self.iter.next();
+        // Skip the samples belonging to the other channels.
+        if self.channels > 1 {
+            self.iter.nth(self.channels - 2);
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.iter.len() + self.channels - 1) / self.channels;
+        (len, Some(len))
+    }
+}
+
+impl<'a, S> Iterator for ChannelMut<'a, S> {
+    type Item = &'a mut S;
+
+    fn next(&mut self) -> Option<&'a mut S> {
+        let sample = self.iter.next();
+        // Skip the samples belonging to the other channels.
+        if self.channels > 1 {
+            self.iter.nth(self.channels - 2);
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.iter.len() + self.channels - 1) / self.channels;
+        (len, Some(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Buffer, BufferMut};
+
+    #[test]
+    fn channel_yields_every_sample_of_one_channel() {
+        let samples = [0, 10, 20, 1, 11, 21, 2, 12, 22];
+        let buffer = Buffer::new(&samples, 3);
+        assert_eq!(buffer.channel(0).cloned().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(buffer.channel(2).cloned().collect::<Vec<_>>(), vec![20, 21, 22]);
+
+        let mut channel = buffer.channel(1);
+        assert_eq!(channel.size_hint(), (3, Some(3)));
+        channel.next();
+        assert_eq!(channel.size_hint(), (2, Some(2)));
+        assert_eq!(channel.cloned().collect::<Vec<_>>(), vec![11, 12]);
+
+        let mono = [1, 2];
+        assert_eq!(Buffer::new(&mono, 1).channel(0).cloned().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn channel_mut_writes_only_its_own_channel() {
+        let mut samples = [0; 6];
+        {
+            let mut buffer = BufferMut::new(&mut samples, 2);
+            for (i, sample) in buffer.channel_mut(1).enumerate() {
+                *sample = i + 1;
+            }
+        }
+        assert_eq!(samples, [0, 1, 0, 2, 0, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn channel_out_of_range_panics() {
+        let samples = [0; 4];
+        Buffer::new(&samples, 2).channel(2);
+    }
+
+    #[test]
+    fn as_frames_requires_matching_channels() {
+        let samples = [0.0, 1.0, 2.0, 3.0];
+        let buffer = Buffer::new(&samples, 2);
+        assert_eq!(buffer.as_frames::<[f32; 2]>(), Some(&[[0.0, 1.0], [2.0, 3.0]][..]));
+        assert_eq!(buffer.as_frames::<[f32; 4]>(), None);
+
+        let mut samples = [0.0f32; 4];
+        {
+            let mut buffer = BufferMut::new(&mut samples, 2);
+            buffer.as_frames_mut::<[f32; 2]>().unwrap()[1] = [4.0, 5.0];
+            assert!(buffer.as_frames_mut::<[f32; 1]>().is_none());
+        }
+        assert_eq!(samples, [0.0, 0.0, 4.0, 5.0]);
+    }
+
+}
@@ -0,0 +1,141 @@
+//! 
+//! Types and functions for discovering the audio devices available on the system.
+//!
+
+use backend::{Backend, Host, PortAudio};
+use error::Error;
+use stream::{Idx, Latency};
+
+#[cfg(feature="regex")]
+use regex::Regex;
+
+/// Information about an audio device, as reported by a `Backend`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    /// The index of the device. May be given to `StreamParams::device_idx`.
+    pub idx: Idx,
+    /// The name of the device.
+    pub name: String,
+    /// The name of the host API that provides the device (i.e. "ALSA", "Core Audio").
+    pub host_api: String,
+    /// The maximum number of input channels supported by the device.
+    pub max_input_channels: i32,
+    /// The maximum number of output channels supported by the device.
+    pub max_output_channels: i32,
+    /// The default input latency for interactive performance.
+    pub default_low_input_latency: Latency,
+    /// The default output latency for interactive performance.
+    pub default_low_output_latency: Latency,
+    /// The default input latency for robust, non-interactive applications.
+    pub default_high_input_latency: Latency,
+    /// The default output latency for robust, non-interactive applications.
+    pub default_high_output_latency: Latency,
+    /// The default sample rate of the device.
+    pub default_sample_hz: f64,
+}
+
+/// A pattern for selecting a device by its name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceName {
+    /// Matches devices whose name contains the given string, ignoring case.
+    Contains(String),
+    /// Matches devices whose name matches the given regular expression.
+    #[cfg(feature="regex")]
+    Regex(String),
+}
+
+/// A `DeviceName` prepared for matching against many device names.
+pub enum NameMatcher {
+    /// The lowercase substring.
+    Contains(String),
+    /// The compiled regular expression.
+    #[cfg(feature="regex")]
+    Regex(Regex),
+}
+
+impl DeviceName {
+
+    /// Whether or not the given device name matches the pattern.
+    pub fn matches(&self, name: &str) -> Result<bool, Error> {
+        Ok(self.matcher()?.matches(name))
+    }
+
+    /// Prepare the pattern for matching, compiling it if it is a regular expression.
+    pub fn matcher(&self) -> Result<NameMatcher, Error> {
+        match *self {
+            DeviceName::Contains(ref pattern) => Ok(NameMatcher::Contains(pattern.to_lowercase())),
+            #[cfg(feature="regex")]
+            DeviceName::Regex(ref pattern) => match Regex::new(pattern) {
+                Ok(regex) => Ok(NameMatcher::Regex(regex)),
+                Err(err) => Err(Error::InvalidDevicePattern(pattern.clone(), err.to_string())),
+            },
+        }
+    }
+
+}
+
+impl NameMatcher {
+
+    /// Whether or not the given device name matches.
+    pub fn matches(&self, name: &str) -> bool {
+        match *self {
+            NameMatcher::Contains(ref pattern) => name.to_lowercase().contains(pattern),
+            #[cfg(feature="regex")]
+            NameMatcher::Regex(ref regex) => regex.is_match(name),
+        }
+    }
+
+    /// Whether or not the given device name is exactly the substring, ignoring case.
+    ///
+    /// Regular expressions never match exactly, as they already say how much of the name to match.
+    pub fn is_exact(&self, name: &str) -> bool {
+        match *self {
+            NameMatcher::Contains(ref pattern) => name.to_lowercase() == *pattern,
+            #[cfg(feature="regex")]
+            NameMatcher::Regex(_) => false,
+        }
+    }
+
+}
+
+impl ::std::fmt::Display for DeviceName {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        match *self {
+            DeviceName::Contains(ref pattern) => write!(f, "name containing {:?}", pattern),
+            #[cfg(feature="regex")]
+            DeviceName::Regex(ref pattern) => write!(f, "name matching /{}/", pattern),
+        }
+    }
+}
+
+impl DeviceInfo {
+
+    /// Whether or not the device supports input.
+    #[inline]
+    pub fn is_input(&self) -> bool {
+        self.max_input_channels > 0
+    }
+
+    /// Whether or not the device supports output.
+    #[inline]
+    pub fn is_output(&self) -> bool {
+        self.max_output_channels > 0
+    }
+
+}
+
+/// All devices available via the default PortAudio backend.
+pub fn devices() -> Result<Vec<DeviceInfo>, Error> {
+    let host = Host::new(PortAudio)?;
+    host.backend().devices()
+}
+
+/// All devices that support input via the default PortAudio backend.
+pub fn input_devices() -> Result<Vec<DeviceInfo>, Error> {
+    devices().map(|devices| devices.into_iter().filter(|device| device.is_input()).collect())
+}
+
+/// All devices that support output via the default PortAudio backend.
+pub fn output_devices() -> Result<Vec<DeviceInfo>, Error> {
+    devices().map(|devices| devices.into_iter().filter(|device| device.is_output()).collect())
+}
@@ -0,0 +1,151 @@
+//!
+//! Diagnostics emitted by streams in place of printing to stdout.
+//!
+
+use error::{Error, Xrun};
+use std::fmt;
+use std::sync::Arc;
+
+/// A diagnostic event emitted by a stream.
+#[derive(Clone, Debug)]
+pub enum Diagnostic {
+    /// An under or overflow was reported while waiting on a blocking stream.
+    Xrun(Xrun),
+    /// A blocking stream has stopped yielding events as the given operation failed.
+    BlockingStreamEnded(StreamOperation, Error),
+    /// An error occurred while closing a stream as it was dropped.
+    CloseFailed(Error),
+}
+
+/// The operations performed on a blocking stream.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StreamOperation {
+    /// Requesting the number of frames available for reading.
+    ReadAvailable,
+    /// Reading from the input stream.
+    Read,
+    /// Requesting the number of frames available for writing.
+    WriteAvailable,
+    /// Writing to the output stream.
+    Write,
+}
+
+/// Receives the diagnostics emitted by streams.
+///
+/// Implemented for all `Fn(&Diagnostic)` closures.
+pub trait DiagnosticSink {
+    /// Handle the given diagnostic.
+    fn emit(&self, diagnostic: &Diagnostic);
+}
+
+/// A shareable handle to a `DiagnosticSink`.
+///
+/// By default diagnostics are written to stderr, or are forwarded to the `log` crate when the
+/// "log" feature is enabled.
+#[derive(Clone)]
+pub struct Diagnostics(Arc<DiagnosticSink>);
+
+/// The sink used when none is given.
+struct DefaultSink;
+
+impl Diagnostic {
+
+    /// Whether or not the diagnostic describes an error, rather than a recoverable glitch.
+    pub fn is_error(&self) -> bool {
+        match *self {
+            Diagnostic::Xrun(_) => false,
+            Diagnostic::BlockingStreamEnded(..) | Diagnostic::CloseFailed(_) => true,
+        }
+    }
+
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::Xrun(ref xrun) => write!(f, "{}", xrun),
+            Diagnostic::BlockingStreamEnded(operation, ref err) =>
+                write!(f, "an error occurred while {}: {}. BlockingStream will now exit the event \
+                           loop", operation, err),
+            Diagnostic::CloseFailed(ref err) =>
+                write!(f, "an error occurred while closing the stream: {}", err),
+        }
+    }
+}
+
+impl fmt::Display for StreamOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            StreamOperation::ReadAvailable =>
+                "requesting the number of available frames for reading from the input stream",
+            StreamOperation::Read => "reading from the input stream",
+            StreamOperation::WriteAvailable =>
+                "requesting the number of available frames for writing to the output stream",
+            StreamOperation::Write => "writing to the output stream",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl<F> DiagnosticSink for F where F: Fn(&Diagnostic) {
+    fn emit(&self, diagnostic: &Diagnostic) {
+        (*self)(diagnostic)
+    }
+}
+
+impl DiagnosticSink for DefaultSink {
+
+    #[cfg(feature="log")]
+    fn emit(&self, diagnostic: &Diagnostic) {
+        if diagnostic.is_error() {
+            error!("{}", diagnostic);
+        } else {
+            warn!("{}", diagnostic);
+        }
+    }
+
+    #[cfg(not(feature="log"))]
+    fn emit(&self, diagnostic: &Diagnostic) {
+        use std::io::Write;
+        let _ = writeln!(::std::io::stderr(), "sound_stream: {}", diagnostic);
+    }
+
+}
+
+impl Diagnostics {
+
+    /// Construct a handle to the given sink.
+    pub fn new<S>(sink: S) -> Diagnostics where S: DiagnosticSink + 'static {
+        Diagnostics(Arc::new(sink))
+    }
+
+    /// A sink that discards all diagnostics.
+    pub fn ignore() -> Diagnostics {
+        Diagnostics::new(|_: &Diagnostic| ())
+    }
+
+    /// Pass the given diagnostic to the sink.
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        self.0.emit(&diagnostic)
+    }
+
+}
+
+impl Default for Diagnostics {
+    fn default() -> Diagnostics {
+        Diagnostics::new(DefaultSink)
+    }
+}
+
+impl PartialEq for Diagnostics {
+    fn eq(&self, other: &Diagnostics) -> bool {
+        ::std::ptr::eq(&*self.0 as *const DiagnosticSink as *const u8,
+                       &*other.0 as *const DiagnosticSink as *const u8)
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Diagnostics")
+    }
+}
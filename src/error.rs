@@ -2,27 +2,117 @@
 //! The sound_stream Error type.
 //!
 
+use format::StreamFormat;
 use portaudio::pa::error::Error as PortAudioError;
 
 /// A type for representing errors in sound_stream.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum Error {
-    /// Errors returned by rust-portaudio.
+    /// Errors returned by rust-portaudio that have no more specific variant.
     PortAudio(PortAudioError),
+    /// The backend could not be initialized. Contains the reason given by the backend.
+    BackendUnavailable(String),
+    /// No device matched the requested device, described by the given string.
+    DeviceNotFound(String),
+    /// More than one device matched the requested device. Contains a description of the
+    /// requested device along with the names of all matching devices.
+    AmbiguousDevice(String, Vec<String>),
+    /// The given device name pattern could not be parsed. Contains the pattern and the reason.
+    InvalidDevicePattern(String, String),
+    /// The device cannot provide the requested number of channels.
+    UnsupportedChannels {
+        /// The name of the device.
+        device: String,
+        /// The number of channels requested.
+        requested: i32,
+        /// The maximum number of channels supported by the device in the requested direction.
+        max: i32,
+    },
+    /// The requested combination of device(s), channels, sample format and sample rate is not
+    /// supported.
+    UnsupportedFormat(StreamFormat),
+    /// A requested setting was invalid regardless of the device. Contains a description.
+    InvalidSettings(String),
+    /// The stream has already been closed.
+    StreamClosed,
+    /// The stream's input overflowed or its output underflowed.
+    Xrun(Xrun),
+}
+
+/// The kinds of buffer overflow and underflow that may occur on a stream.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Xrun {
+    /// Input data was discarded because it was not read in time.
+    InputOverflow,
+    /// Silence was inserted because output data was not written in time.
+    OutputUnderflow,
+}
+
+impl From<PortAudioError> for Error {
+    fn from(err: PortAudioError) -> Error {
+        match err {
+            PortAudioError::InputOverflowed => Error::Xrun(Xrun::InputOverflow),
+            PortAudioError::OutputUnderflowed => Error::Xrun(Xrun::OutputUnderflow),
+            err => Error::PortAudio(err),
+        }
+    }
 }
 
 impl ::std::fmt::Display for Error {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        write!(f, "{:?}", self)
+        use self::Error::*;
+        match *self {
+            PortAudio(ref err) => write!(f, "PortAudio error: {}", err),
+            BackendUnavailable(ref reason) =>
+                write!(f, "the audio backend is unavailable: {}", reason),
+            DeviceNotFound(ref device) => write!(f, "no {} could be found", device),
+            AmbiguousDevice(ref device, ref matches) =>
+                write!(f, "more than one {} was found: {}", device, matches.join(", ")),
+            InvalidDevicePattern(ref pattern, ref reason) =>
+                write!(f, "invalid device name pattern {:?}: {}", pattern, reason),
+            UnsupportedChannels { ref device, requested, max } =>
+                write!(f, "{:?} supports at most {} channel(s), but {} were requested",
+                       device, max, requested),
+            UnsupportedFormat(ref format) => write!(f, "unsupported stream format: {}", format),
+            InvalidSettings(ref reason) => write!(f, "invalid stream settings: {}", reason),
+            StreamClosed => write!(f, "the stream has already been closed"),
+            Xrun(ref xrun) => write!(f, "{}", xrun),
+        }
+    }
+}
+
+impl ::std::fmt::Display for Xrun {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        match *self {
+            Xrun::InputOverflow => write!(f, "the input stream overflowed"),
+            Xrun::OutputUnderflow => write!(f, "the output stream underflowed"),
+        }
     }
 }
 
+// `description` and `cause` are deprecated but still implemented for older callers.
+#[allow(deprecated)]
 impl ::std::error::Error for Error {
     fn description(&self) -> &str {
         use self::Error::*;
         match *self {
             PortAudio(ref err) => err.description(),
+            BackendUnavailable(_) => "the audio backend is unavailable",
+            DeviceNotFound(_) => "no device matched the requested device",
+            AmbiguousDevice(_, _) => "more than one device matched the requested device",
+            InvalidDevicePattern(_, _) => "the device name pattern could not be parsed",
+            UnsupportedChannels { .. } => "the device cannot provide the requested channels",
+            UnsupportedFormat(_) => "the requested stream format is not supported",
+            InvalidSettings(_) => "the requested stream settings are invalid",
+            StreamClosed => "the stream has already been closed",
+            Xrun(_) => "the stream's input overflowed or its output underflowed",
         }
     }
-}
 
+    fn cause(&self) -> Option<&::std::error::Error> {
+        match *self {
+            Error::PortAudio(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! 
+//! Types describing the format of a stream as requested from a device.
+//!
+
+use portaudio::pa;
+use stream::Idx;
+
+/// The format requested of a single device within a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceFormat {
+    /// The index of the device.
+    pub idx: Idx,
+    /// The name of the device.
+    pub device: String,
+    /// The requested number of channels.
+    pub channels: i32,
+    /// The requested sample format.
+    pub sample_format: pa::SampleFormat,
+}
+
+/// The format requested of the device(s) used by a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamFormat {
+    /// The requested sample rate.
+    pub sample_hz: f64,
+    /// The format requested of the input device, if any.
+    pub input: Option<DeviceFormat>,
+    /// The format requested of the output device, if any.
+    pub output: Option<DeviceFormat>,
+}
+
+impl ::std::fmt::Display for DeviceFormat {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "{} channel(s) of {:?} on {:?}", self.channels, self.sample_format, self.device)
+    }
+}
+
+impl ::std::fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        if let Some(ref input) = self.input {
+            write!(f, "input of {}, ", input)?;
+        }
+        if let Some(ref output) = self.output {
+            write!(f, "output of {}, ", output)?;
+        }
+        write!(f, "at {}hz", self.sample_hz)
+    }
+}
@@ -1,17 +1,38 @@
+// The crate keeps to the 2015 edition's bare trait objects.
+#![allow(bare_trait_objects)]
 
+
+#[cfg(feature="futures")]
+extern crate atomic_waker;
+#[cfg(feature="futures")]
+extern crate futures_core;
+#[cfg(feature="futures")]
+extern crate futures_sink;
+#[cfg(feature="log")]
+#[macro_use]
+extern crate log;
 extern crate num;
-extern crate portaudio as portaudio_lib;
+pub extern crate portaudio as portaudio_lib;
+#[cfg(feature="regex")]
+extern crate regex;
 extern crate sample;
 extern crate time;
 
 
 pub use portaudio_lib as portaudio;
 
-pub use error::Error;
+pub use backend::{Backend, Host, Offline, PortAudio, RawCallback, RawStream};
+pub use buffer::{Buffer, BufferMut, Channel, ChannelMut, Frame};
+pub use diagnostics::{Diagnostic, DiagnosticSink, Diagnostics, StreamOperation};
+pub use device::{DeviceInfo, DeviceName, NameMatcher, devices, input_devices, output_devices};
+pub use error::{Error, Xrun};
+pub use format::{DeviceFormat, StreamFormat};
+pub use planar::{Planar, PlanarMut};
 pub use portaudio::pa::Sample as PaSample;
 pub use portaudio::pa::Stream as PaStream;
 pub use sample::{Amplitude, Sample, Wave};
 pub use settings::{Settings, SampleHz, Frames, Channels};
+pub use spsc::{Consumer, Drain, Producer, Receiver, Sender};
 pub use stream::Idx as DeviceIdx;
 pub use stream::{
     input,
@@ -24,10 +45,19 @@ pub use stream::{
     SoundStream,
     StreamFlags,
     StreamParams,
+    StreamTime,
+    XrunStats,
 };
 
+mod backend;
+mod buffer;
+mod device;
+mod diagnostics;
 mod error;
+mod format;
+mod planar;
 mod settings;
+pub mod spsc;
 mod stream;
 mod utils;
 
@@ -0,0 +1,170 @@
+//!
+//! Non-interleaved (planar) views of a stream's buffers.
+//!
+//! Each channel's samples are stored contiguously, one channel after another, rather than frame
+//! by frame. This suits DSP that is written to process a single channel at a time.
+//!
+
+use std::slice;
+
+/// A planar view of a buffer of samples.
+#[derive(Copy, Clone, Debug)]
+pub struct Planar<'a, S: 'a> {
+    samples: &'a [S],
+    channels: usize,
+}
+
+/// A mutable planar view of a buffer of samples.
+#[derive(Debug)]
+pub struct PlanarMut<'a, S: 'a> {
+    samples: &'a mut [S],
+    channels: usize,
+}
+
+impl<'a, S> Planar<'a, S> {
+
+    /// View the given channel-major samples as a buffer with the given number of channels.
+    ///
+    /// **Panics** if `channels` is `0` or does not evenly divide the number of samples.
+    pub fn new(samples: &'a [S], channels: usize) -> Planar<'a, S> {
+        assert!(channels > 0 && samples.len() % channels == 0,
+                "the number of samples must be a multiple of the number of channels");
+        Planar { samples, channels }
+    }
+
+    /// The number of channels.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The number of frames, equal to the length of each channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// The samples of the channel at the given index.
+    ///
+    /// **Panics** if `idx` is out of range.
+    pub fn channel(&self, idx: usize) -> &'a [S] {
+        let frames = self.frames();
+        &self.samples[idx * frames..(idx + 1) * frames]
+    }
+
+    /// An iterator yielding the samples of each channel in order.
+    pub fn iter(&self) -> slice::Chunks<'a, S> {
+        self.samples.chunks(::std::cmp::max(self.frames(), 1))
+    }
+
+    /// All samples, channel by channel.
+    pub fn samples(&self) -> &'a [S] {
+        self.samples
+    }
+
+}
+
+impl<'a, S> PlanarMut<'a, S> {
+
+    /// View the given channel-major samples as a buffer with the given number of channels.
+    ///
+    /// **Panics** if `channels` is `0` or does not evenly divide the number of samples.
+    pub fn new(samples: &'a mut [S], channels: usize) -> PlanarMut<'a, S> {
+        assert!(channels > 0 && samples.len() % channels == 0,
+                "the number of samples must be a multiple of the number of channels");
+        PlanarMut { samples, channels }
+    }
+
+    /// The number of channels.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The number of frames, equal to the length of each channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// The samples of the channel at the given index.
+    ///
+    /// **Panics** if `idx` is out of range.
+    pub fn channel(&self, idx: usize) -> &[S] {
+        let frames = self.frames();
+        &self.samples[idx * frames..(idx + 1) * frames]
+    }
+
+    /// The mutable samples of the channel at the given index.
+    ///
+    /// **Panics** if `idx` is out of range.
+    pub fn channel_mut(&mut self, idx: usize) -> &mut [S] {
+        let frames = self.frames();
+        &mut self.samples[idx * frames..(idx + 1) * frames]
+    }
+
+    /// An iterator yielding the samples of each channel in order.
+    pub fn iter<'b>(&'b self) -> slice::Chunks<'b, S> {
+        self.samples.chunks(::std::cmp::max(self.frames(), 1))
+    }
+
+    /// An iterator yielding the mutable samples of each channel in order.
+    pub fn iter_mut<'b>(&'b mut self) -> slice::ChunksMut<'b, S> {
+        let frames = ::std::cmp::max(self.frames(), 1);
+        self.samples.chunks_mut(frames)
+    }
+
+    /// All samples, channel by channel.
+    pub fn samples(&self) -> &[S] {
+        self.samples
+    }
+
+    /// All mutable samples, channel by channel.
+    pub fn samples_mut(&mut self) -> &mut [S] {
+        self.samples
+    }
+
+    /// Reborrow as an immutable view.
+    pub fn as_planar<'b>(&'b self) -> Planar<'b, S> {
+        Planar { samples: self.samples, channels: self.channels }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Planar, PlanarMut};
+    use utils::{deinterleave, interleave};
+
+    #[test]
+    fn deinterleave_and_interleave_round_trip() {
+        let interleaved = [0, 10, 20, 1, 11, 21, 2, 12, 22];
+        let mut planar = [0; 9];
+        deinterleave(&interleaved, &mut planar, 3);
+        assert_eq!(planar, [0, 1, 2, 10, 11, 12, 20, 21, 22]);
+        assert_eq!(Planar::new(&planar, 3).channel(1), &[10, 11, 12]);
+
+        let mut round_trip = [0; 9];
+        interleave(&planar, &mut round_trip, 3);
+        assert_eq!(round_trip, interleaved);
+    }
+
+    #[test]
+    fn channel_mut_indexes_each_channel() {
+        let mut samples = [0; 6];
+        {
+            let mut planar = PlanarMut::new(&mut samples, 2);
+            assert_eq!(planar.frames(), 3);
+            for (i, sample) in planar.channel_mut(1).iter_mut().enumerate() {
+                *sample = i + 1;
+            }
+            planar.channel_mut(0)[2] = 9;
+            assert_eq!(planar.channel(0), &[0, 0, 9]);
+        }
+        assert_eq!(samples, [0, 0, 9, 1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn channel_mut_out_of_range_panics() {
+        let mut samples = [0; 4];
+        PlanarMut::new(&mut samples, 2).channel_mut(2);
+    }
+
+}
@@ -1,13 +1,18 @@
 
 pub type SampleHz = u32;
-pub type Frames = u16;
+pub type Frames = u32;
 pub type Channels = u16;
 
 /// Settings required for SoundStream.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Settings {
-    /// The number of samples per second.
+    /// The number of samples per second, rounded to the nearest integer.
     pub sample_hz: SampleHz,
+    /// The exact number of samples per second.
+    ///
+    /// For the settings of an open stream this is the rate reported by the device, which may
+    /// differ slightly from the one requested.
+    pub exact_sample_hz: f64,
     /// How many samples per channel requested at a time in the buffer.
     /// The more frames, the less likely to make glitches,
     /// but this gives slower response.
@@ -21,9 +26,22 @@ impl Settings {
     /// Custom constructor for the Settings.
     pub fn new(sample_hz: SampleHz, frames: Frames, channels: Channels) -> Settings {
         Settings {
-            sample_hz: sample_hz,
-            frames: frames,
-            channels: channels
+            sample_hz,
+            exact_sample_hz: sample_hz as f64,
+            frames,
+            channels
+        }
+    }
+
+    /// Constructor for Settings with a fractional sample rate, i.e. 44099.9 or 29.97 * 1600.
+    ///
+    /// `sample_hz` is rounded to the nearest integer.
+    pub fn from_exact_sample_hz(sample_hz: f64, frames: Frames, channels: Channels) -> Settings {
+        Settings {
+            sample_hz: sample_hz.round() as SampleHz,
+            exact_sample_hz: sample_hz,
+            frames,
+            channels
         }
     }
 
@@ -31,6 +49,7 @@ impl Settings {
     pub fn cd_quality() -> Settings {
         Settings {
             sample_hz: 44100,
+            exact_sample_hz: 44100.0,
             frames: 256,
             channels: 2
         }
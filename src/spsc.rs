@@ -0,0 +1,356 @@
+//!
+//! A bounded, wait-free, single-producer single-consumer queue.
+//!
+//! Used to send messages to and from the audio thread without locking, allocating or blocking.
+//!
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The ring buffer shared between a `Sender` and a `Receiver`.
+struct Buffer<T> {
+    /// A power of two number of slots, so that the wrapping counters below map onto them
+    /// consistently.
+    slots: Vec<UnsafeCell<Option<T>>>,
+    /// The number of values that may be held at once, which may be fewer than the slots.
+    capacity: usize,
+    /// The total number of values received. Only written by the `Receiver`.
+    head: AtomicUsize,
+    /// The total number of values sent. Only written by the `Sender`.
+    tail: AtomicUsize,
+}
+
+/// The sending half of a channel.
+pub struct Sender<T> {
+    buffer: Arc<Buffer<T>>,
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    buffer: Arc<Buffer<T>>,
+}
+
+/// The producing half of a channel that counts the values that could not be sent.
+///
+/// Suitable for sending data out of the audio thread, where waiting on a slow consumer is not
+/// an option.
+pub struct Producer<T> {
+    sender: Sender<T>,
+    overflows: Arc<AtomicUsize>,
+}
+
+/// The consuming half of a channel that counts the values that could not be sent.
+pub struct Consumer<T> {
+    receiver: Receiver<T>,
+    overflows: Arc<AtomicUsize>,
+}
+
+/// An iterator yielding the values that were pending when it was created.
+///
+/// Values that are not iterated over remain in the queue.
+pub struct Drain<'a, T: 'a> {
+    receiver: &'a mut Receiver<T>,
+    remaining: usize,
+}
+
+// The `Sender` and `Receiver` never access the same slot at the same time, so the buffer may be
+// shared between threads as long as the values themselves may be sent.
+unsafe impl<T: Send> Send for Buffer<T> {}
+unsafe impl<T: Send> Sync for Buffer<T> {}
+
+/// Construct a channel that can hold up to `capacity` values at once.
+///
+/// **Panics** if `capacity` is `0`.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "the capacity of a channel must be greater than 0");
+    let buffer = Arc::new(Buffer::new(capacity));
+    (Sender { buffer: buffer.clone() }, Receiver { buffer })
+}
+
+/// Construct a channel that can hold up to `capacity` values at once and that counts each value
+/// the `Producer` is unable to send because the `Consumer` has fallen behind.
+///
+/// **Panics** if `capacity` is `0`.
+pub fn counting_channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let (sender, receiver) = channel(capacity);
+    let overflows = Arc::new(AtomicUsize::new(0));
+    let producer = Producer { sender, overflows: overflows.clone() };
+    let consumer = Consumer { receiver, overflows };
+    (producer, consumer)
+}
+
+impl<T> Buffer<T> {
+
+    /// Construct an empty buffer that can hold up to `capacity` values at once.
+    fn new(capacity: usize) -> Buffer<T> {
+        let len = capacity.next_power_of_two();
+        Buffer {
+            slots: (0..len).map(|_| UnsafeCell::new(None)).collect(),
+            capacity,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of values that may be held at once.
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The slot for the value at the given position. As the number of slots is a power of two,
+    /// this remains correct once the position wraps around.
+    fn slot(&self, position: usize) -> &UnsafeCell<Option<T>> {
+        &self.slots[position & (self.slots.len() - 1)]
+    }
+
+    /// The number of values currently in the queue.
+    fn len(&self) -> usize {
+        // Load the head first so that it can never be ahead of the tail.
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+}
+
+impl<T> Sender<T> {
+
+    /// Attempt to send a value without blocking.
+    ///
+    /// Returns the value back as an `Err` if the queue is full.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        let buffer = &*self.buffer;
+        let tail = buffer.tail.load(Ordering::Relaxed);
+        let head = buffer.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == buffer.capacity() {
+            return Err(value);
+        }
+        unsafe {
+            *buffer.slot(tail).get() = Some(value);
+        }
+        buffer.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// The number of values that may be held at once.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// The number of values waiting to be received.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether or not there are no values waiting to be received.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether or not the queue is full.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+}
+
+impl<T> Receiver<T> {
+
+    /// Attempt to receive a value without blocking.
+    ///
+    /// Returns `None` if the queue is empty.
+    pub fn try_recv(&mut self) -> Option<T> {
+        let buffer = &*self.buffer;
+        let head = buffer.head.load(Ordering::Relaxed);
+        let tail = buffer.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { (*buffer.slot(head).get()).take() };
+        buffer.head.store(head.wrapping_add(1), Ordering::Release);
+        value
+    }
+
+    /// An iterator yielding all values that are currently waiting to be received.
+    ///
+    /// Values sent while iterating are left for the next call, so the amount of work done is
+    /// bounded by the capacity of the queue.
+    pub fn drain<'a>(&'a mut self) -> Drain<'a, T> {
+        let remaining = self.len();
+        Drain { receiver: self, remaining }
+    }
+
+    /// The number of values that may be held at once.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// The number of values waiting to be received.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether or not there are no values waiting to be received.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+}
+
+impl<T> Producer<T> {
+
+    /// Attempt to send a value without blocking.
+    ///
+    /// If the queue is full the overflow is counted and the value is returned as an `Err`.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let result = self.sender.try_send(value);
+        if result.is_err() {
+            self.count_overflow();
+        }
+        result
+    }
+
+    /// Count a value that was dropped before it could be pushed.
+    pub fn count_overflow(&self) {
+        self.overflows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of values that may be held at once.
+    pub fn capacity(&self) -> usize {
+        self.sender.capacity()
+    }
+
+    /// Whether or not the queue is full.
+    pub fn is_full(&self) -> bool {
+        self.sender.is_full()
+    }
+
+}
+
+impl<T> Consumer<T> {
+
+    /// Attempt to receive a value without blocking.
+    ///
+    /// Returns `None` if the queue is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        self.receiver.try_recv()
+    }
+
+    /// An iterator yielding all values that are currently waiting to be received.
+    pub fn drain<'a>(&'a mut self) -> Drain<'a, T> {
+        self.receiver.drain()
+    }
+
+    /// The total number of values that the `Producer` has failed to send.
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of values that the `Producer` has failed to send and reset the count.
+    pub fn take_overflows(&self) -> usize {
+        self.overflows.swap(0, Ordering::Relaxed)
+    }
+
+    /// The number of values that may be held at once.
+    pub fn capacity(&self) -> usize {
+        self.receiver.capacity()
+    }
+
+    /// The number of values waiting to be received.
+    pub fn len(&self) -> usize {
+        self.receiver.len()
+    }
+
+    /// Whether or not there are no values waiting to be received.
+    pub fn is_empty(&self) -> bool {
+        self.receiver.is_empty()
+    }
+
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.receiver.try_recv()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use super::{Buffer, Receiver, Sender, channel};
+
+    /// A channel whose counters start at the given position.
+    fn channel_at<T>(capacity: usize, position: usize) -> (Sender<T>, Receiver<T>) {
+        let buffer = Buffer::new(capacity);
+        buffer.head.store(position, Ordering::Relaxed);
+        buffer.tail.store(position, Ordering::Relaxed);
+        let buffer = Arc::new(buffer);
+        (Sender { buffer: buffer.clone() }, Receiver { buffer })
+    }
+
+    #[test]
+    fn wraps_around() {
+        // A capacity that is not a power of two, with the counters about to wrap.
+        let (mut sender, mut receiver) = channel_at(3, usize::MAX - 4);
+        let mut next_sent = 0;
+        let mut next_received = 0;
+        for _ in 0..8 {
+            while sender.try_send(next_sent).is_ok() {
+                next_sent += 1;
+            }
+            assert_eq!(sender.len(), 3);
+            // Leave a value behind so that the queue straddles the wrap.
+            for _ in 0..2 {
+                assert_eq!(receiver.try_recv(), Some(next_received));
+                next_received += 1;
+            }
+        }
+        while let Some(value) = receiver.try_recv() {
+            assert_eq!(value, next_received);
+            next_received += 1;
+        }
+        assert_eq!(next_received, next_sent);
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn concurrent_send_and_receive() {
+        const COUNT: usize = 10_000;
+        let (mut sender, mut receiver) = channel(7);
+        let sending = thread::spawn(move || {
+            let mut value = 0;
+            while value < COUNT {
+                match sender.try_send(value) {
+                    Ok(()) => value += 1,
+                    Err(_) => thread::yield_now(),
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < COUNT {
+            match receiver.try_recv() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                },
+                None => thread::yield_now(),
+            }
+        }
+        sending.join().unwrap();
+        assert!(receiver.try_recv().is_none());
+    }
+
+}
@@ -1,86 +1,162 @@
 
+use backend::{Backend, Host, PortAudio, RawStream};
+use buffer::{Buffer, BufferMut};
+use diagnostics::{Diagnostic, Diagnostics, StreamOperation};
 use error::Error;
+use format::StreamFormat;
+use planar::{Planar, PlanarMut};
 use portaudio::pa;
 use portaudio::pa::Sample as PaSample;
 use sample::{Sample, Wave};
-use settings::{Channels, Settings, Frames, SampleHz};
+use settings::{Channels, Settings, Frames};
+use spsc::{self, Consumer, Drain, Producer, Sender};
 use std::collections::VecDeque;
-use std::marker::PhantomData;
-use utils::take_front;
+use utils::{deinterleave, drain_front, interleave};
 
 use super::{
     BufferFrequency,
     CallbackFlags,
     CallbackResult,
-    DeltaTimeSeconds,
+    Direction,
+    FramesProcessed,
     MINIMUM_BUFFER_RESERVATION,
+    SharedSampleHz,
     SoundStream,
     StreamFlags,
     StreamParams,
+    StreamTime,
+    Timer,
+    XrunCounter,
+    XrunStats,
+    check_format,
+    for_each_chunk,
+    format_error,
+    planar_frames,
     wait_for_stream,
 };
 
 
 /// A builder context for a duplex sound stream.
-pub struct Builder<I, O> {
-    pub stream_params: SoundStream,
+pub struct Builder<I, O, B=PortAudio> {
+    pub stream_params: SoundStream<B>,
     pub input_params: StreamParams<I>,
     pub output_params: StreamParams<O>,
 }
 
 
-/// An iterator of blocking stream events.
-pub struct BlockingStream<'a, I=Wave, O=Wave>
+/// A blocking duplex stream, yielding each event via `next_event`.
+pub struct BlockingStream<I=Wave, O=Wave, B=PortAudio>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
     /// Buffer the samples from the input until its length is equal to the buffer_length.
     input_buffer: VecDeque<I>,
     /// Store samples in this until there is enough to write to the output stream.
     output_buffer: VecDeque<O>,
+    /// The samples most recently read from the input stream.
+    read_buffer: Vec<I>,
+    /// The buffer lent to the user by each `In` event.
+    event_buffer: Vec<I>,
     /// A buffer for retrieving samples from the user for writing.
     user_buffer: Vec<O>,
+    /// The samples taken from the front of the `output_buffer` for each write to the stream.
+    write_buffer: Vec<O>,
     /// Number of input channels.
     in_channels: Channels,
     /// Number of output channels.
     out_channels: Channels,
-    /// Stream sample rate.
-    sample_hz: SampleHz,
+    /// The exact stream sample rate.
+    sample_hz: f64,
     /// Frames per buffer.
     frames: Frames,
+    /// The number of frames yielded by `In` events so far.
+    in_frames_processed: u64,
+    /// The number of frames yielded by `Out` events so far.
+    out_frames_processed: u64,
+    /// Where diagnostics are sent.
+    diagnostics: Diagnostics,
+    /// Counts the xruns reported while waiting on the stream.
+    xruns: XrunCounter,
+    /// The error that most recently ended iteration.
+    maybe_last_error: Option<Error>,
     /// The last event that has occured.
     last_event: Option<LastEvent>,
-    /// The port audio stream.
-    stream: pa::Stream<I, O>,
+    /// The backend's stream.
+    stream: Box<RawStream<I, O>>,
+    /// Keeps the backend initialized while the stream is open.
+    host: Host<B>,
+    /// The input parameters negotiated with the device.
+    input_params: StreamParams<I>,
+    /// The output parameters negotiated with the device.
+    output_params: StreamParams<O>,
     is_closed: bool,
-    marker: PhantomData<&'a ()>,
 }
 
 
 /// Stream callback function type.
-pub type Callback<I, O> =
-    Box<FnMut(&[I], Settings, &mut[O], Settings, DeltaTimeSeconds, CallbackFlags) -> CallbackResult>;
+pub type Callback<I, O> = Box<FnMut(Buffer<I>, Settings, BufferMut<O>, Settings, StreamTime,
+                                    CallbackFlags) -> CallbackResult>;
+
+/// Stream callback function type that receives and writes each buffer in planar form.
+pub type PlanarCallback<I, O> = Box<FnMut(Planar<I>, Settings, PlanarMut<O>, Settings, StreamTime,
+                                          CallbackFlags) -> CallbackResult>;
+
+/// Stream callback function type that also receives the messages sent to the stream.
+pub type MessageCallback<I, O, M> = Box<FnMut(Buffer<I>, Settings, BufferMut<O>, Settings,
+                                              StreamTime, CallbackFlags, Drain<M>)
+                                              -> CallbackResult>;
+
+/// Stream callback function type that may also push values out of the audio thread.
+pub type ProducerCallback<I, O, E> = Box<FnMut(Buffer<I>, Settings, BufferMut<O>, Settings,
+                                               StreamTime, CallbackFlags, &mut Producer<E>)
+                                               -> CallbackResult>;
 
 /// A handle to the non-blocking duplex stream.
-pub struct NonBlockingStream<I=Wave, O=Wave>
+pub struct NonBlockingStream<I=Wave, O=Wave, B=PortAudio>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
-    /// The port audio stream.
-    stream: pa::Stream<I, O>,
+    /// The backend's stream.
+    stream: Box<RawStream<I, O>>,
+    /// Keeps the backend initialized while the stream is open.
+    host: Host<B>,
+    /// The input parameters negotiated with the device.
+    input_params: StreamParams<I>,
+    /// The output parameters negotiated with the device.
+    output_params: StreamParams<O>,
+    /// The settings with which the stream's input was opened.
+    input_settings: Settings,
+    /// The settings with which the stream's output was opened.
+    output_settings: Settings,
+    /// The number of frames processed by the callback.
+    frames_processed: FramesProcessed,
+    /// Where diagnostics are sent.
+    diagnostics: Diagnostics,
+    /// Counts the xruns reported to the callback.
+    xruns: XrunCounter,
     /// Whether or not the stream is currently closed.
     is_closed: bool,
 }
 
 /// An event to be returned by the BlockingStream.
+///
+/// Each event also holds the index of its buffer's first frame.
 #[derive(Debug)]
-pub enum Event<'a, I=Wave, O=Wave> where O: 'a {
+pub enum Event<'a, I=Wave, O=Wave> where I: 'a, O: 'a {
     /// Audio awaits on the stream's input buffer.
-    In(Vec<I>, Settings),
+    In(&'a [I], Settings, u64),
     /// The stream's output buffer is ready to be written to.
-    Out(&'a mut [O], Settings),
+    Out(&'a mut [O], Settings, u64),
+}
+
+/// The event that the BlockingStream is ready to yield.
+enum NextEvent {
+    In(Settings, u64),
+    Out(Settings, u64),
 }
 
 /// Represents the current state of the BlockingStream.
@@ -94,74 +170,68 @@ pub enum LastEvent {
 /// The params to be unwrapped after the building is complete.
 type PaParams = (StreamFlags, pa::StreamParameters, pa::StreamParameters, f64, u32);
 
-impl<I, O> Builder<I, O>
+impl<I, O, B> Builder<I, O, B>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
 
     /// Retrieve the flags, stream parameters, sample rate and frames per buffer.
-    fn unwrap_params(self) -> Result<PaParams, Error> {
-        let Builder { stream_params, input_params, output_params } = self;
-        let SoundStream { maybe_buffer_frequency, maybe_sample_hz, maybe_flags } = stream_params;
+    fn unwrap_params(&self) -> Result<PaParams, Error> {
+        let Builder { ref stream_params, ref input_params, ref output_params } = *self;
+        let SoundStream {
+            maybe_buffer_frequency, maybe_flags, strict_channels, ref backend, ..
+        } = *stream_params;
+
+        // Check the requested values before querying any devices.
+        stream_params.validate()?;
+        input_params.validate()?;
+        output_params.validate()?;
 
         // Retrieve any stream flags.
-        let flags = maybe_flags.unwrap_or_else(|| StreamFlags::empty());
+        let flags = maybe_flags.unwrap_or_else(StreamFlags::empty);
 
         // Construct the PortAudio input params from the sound stream ones.
         let input_params = {
-            let idx = input_params.idx.unwrap_or_else(|| pa::device::get_default_input());
-            let info = match pa::device::get_info(idx) {
-                Ok(info) => info,
-                Err(err) => return Err(Error::PortAudio(err)),
-            };
-            let channels = input_params.channel_count
-                .map(|n| ::std::cmp::min(n, info.max_input_channels))
-                .unwrap_or_else(|| ::std::cmp::min(2, info.max_input_channels));
+            let idx = input_params.resolve_device(backend, Direction::Input)?;
+            let info = backend.device_info(idx)?;
+            let channels = input_params.negotiate_channels(&info, Direction::Input,
+                                                           strict_channels)?;
             let sample_format = input_params.sample_format();
             let suggested_latency = input_params.suggested_latency
-                .unwrap_or_else(|| info.default_low_input_latency);
+                .unwrap_or(info.default_low_input_latency);
             pa::StreamParameters {
                 device: idx,
                 channel_count: channels,
-                sample_format: sample_format,
-                suggested_latency: suggested_latency,
+                sample_format,
+                suggested_latency,
             }
         };
 
         // Construct the PortAudio output params from the sound stream ones.
         let output_params = {
-            let idx = output_params.idx.unwrap_or_else(|| pa::device::get_default_output());
-            let info = match pa::device::get_info(idx) {
-                Ok(info) => info,
-                Err(err) => return Err(Error::PortAudio(err)),
-            };
-            let channels = output_params.channel_count
-                .map(|n| ::std::cmp::min(n, info.max_output_channels))
-                .unwrap_or_else(|| ::std::cmp::min(2, info.max_output_channels));
+            let idx = output_params.resolve_device(backend, Direction::Output)?;
+            let info = backend.device_info(idx)?;
+            let channels = output_params.negotiate_channels(&info, Direction::Output,
+                                                            strict_channels)?;
             let sample_format = output_params.sample_format();
             let suggested_latency = output_params.suggested_latency
-                .unwrap_or_else(|| info.default_low_output_latency);
+                .unwrap_or(info.default_low_output_latency);
             pa::StreamParameters {
                 device: idx,
                 channel_count: channels,
-                sample_format: sample_format,
-                suggested_latency: suggested_latency,
+                sample_format,
+                suggested_latency,
             }
         };
 
         // Determine the sample rate.
-        let sample_hz = match maybe_sample_hz {
-            Some(sample_hz) => sample_hz,
-            None => match pa::device::get_info(input_params.device) {
-                Ok(info) => info.default_sample_rate,
-                Err(err) => return Err(Error::PortAudio(err)),
-            },
-        };
+        let sample_hz = stream_params.negotiate_sample_hz(Some(&input_params), Some(&output_params))?;
 
         // Determine the closest number of frames per buffer to the requested rate.
         let frames = match maybe_buffer_frequency {
-            Some(BufferFrequency::Frames(frames)) => frames as u32,
+            Some(BufferFrequency::Frames(frames)) => frames,
             Some(BufferFrequency::Hz(hz)) => (sample_hz as f32 / hz).round() as u32,
             None => 0,
         };
@@ -169,79 +239,245 @@ impl<I, O> Builder<I, O>
         Ok((flags, input_params, output_params, sample_hz, frames))
     }
 
+    /// Check whether the devices, channels, sample format and sample rate are supported by the
+    /// backend without starting any audio.
+    ///
+    /// Returns the format that would be requested when the stream is run, or an
+    /// `UnsupportedFormat` error describing it. Note that the number of channels is clamped to
+    /// the device's maximum as it would be when run, unless `strict_channels` was requested.
+    pub fn check(&self) -> Result<StreamFormat, Error> {
+        let backend = &self.stream_params.backend;
+        backend.initialize()?;
+        let result = self.unwrap_params().and_then(|(_, input_params, output_params, sample_hz, _)| {
+            check_format(backend, Some(&input_params), Some(&output_params), sample_hz)
+        });
+        backend.terminate()?;
+        result
+    }
+
+    /// Whether or not the stream's format is supported. See `check` for details.
+    pub fn is_supported(&self) -> bool {
+        self.check().is_ok()
+    }
+
     /// Launch a non-blocking duplex stream with the given callback!
     #[inline]
-    pub fn run_callback(self, mut callback: Callback<I, O>)
-        -> Result<NonBlockingStream<I, O>, Error>
+    pub fn run_callback(self, callback: Callback<I, O>)
+        -> Result<NonBlockingStream<I, O, B>, Error>
         where I: 'static,
               O: 'static,
     {
+        self.run_callback_with(move |_, _| callback)
+    }
 
-        // Initialize PortAudio.
-        try!(pa::initialize().map_err(|err| Error::PortAudio(err)));
+    /// Launch a non-blocking duplex stream with the callback returned by `new_callback`.
+    ///
+    /// `new_callback` is given the negotiated input and output settings before the stream is
+    /// opened, so that any buffers used by the callback may be allocated up front.
+    fn run_callback_with<F>(self, new_callback: F) -> Result<NonBlockingStream<I, O, B>, Error>
+        where I: 'static,
+              O: 'static,
+              F: FnOnce(Settings, Settings) -> Callback<I, O>,
+    {
 
-        let (flags, input_params, output_params, sample_hz, frames) = try!(self.unwrap_params());
+        // Initialize the backend. The `Host` terminates it again should anything below fail.
+        self.stream_params.backend.initialize()?;
+        let params = self.unwrap_params();
+        let host = Host::initialized(self.stream_params.backend);
+        let diagnostics = self.stream_params.diagnostics;
+
+        let (flags, input_params, output_params, sample_hz, frames) = params?;
         let in_channels = input_params.channel_count;
         let out_channels = output_params.channel_count;
+        let mut callback = new_callback(
+            Settings::from_exact_sample_hz(sample_hz, frames, in_channels as u16),
+            Settings::from_exact_sample_hz(sample_hz, frames, out_channels as u16));
+
+        // The callback learns the actual sample rate once the stream has been opened.
+        let shared_sample_hz = SharedSampleHz::new(sample_hz);
+        let callback_sample_hz = shared_sample_hz.clone();
 
-        // Here we construct our PortAudio stream.
-        let mut stream = pa::Stream::new();
+        // Tracks the time and position of each buffer.
+        let mut timer = Timer::new();
+        let frames_processed = timer.frames_processed();
 
-        // Remember the last time the callback was called so we can create the delta time.
-        let mut maybe_last_time = None; 
+        // Count the xruns reported to the callback.
+        let xruns = XrunCounter::new();
+        let callback_xruns = xruns.clone();
 
         // Construct a wrapper function around our callback.
         let f = Box::new(move |input: &[I],
                                output: &mut[O],
                                frames: u32,
                                time_info: &pa::StreamCallbackTimeInfo,
-                               flags: pa::StreamCallbackFlags| -> pa::StreamCallbackResult {
-            let in_settings = Settings {
-                sample_hz: sample_hz as u32,
-                frames: frames as u16,
-                channels: in_channels as u16,
-            };
+                               flags: CallbackFlags| -> CallbackResult {
+            let sample_hz = callback_sample_hz.get();
+            let in_settings = Settings::from_exact_sample_hz(sample_hz, frames, in_channels as u16);
             let out_settings = Settings { channels: out_channels as u16, ..in_settings };
-            let dt = time_info.current_time - maybe_last_time.unwrap_or(time_info.current_time);
-            maybe_last_time = Some(time_info.current_time);
-            match callback(input, in_settings, output, out_settings, dt, flags) {
-                CallbackResult::Continue => pa::StreamCallbackResult::Continue,
-                CallbackResult::Complete => pa::StreamCallbackResult::Complete,
-                CallbackResult::Abort    => pa::StreamCallbackResult::Abort,
-            }
+            let time = timer.next(time_info, frames);
+            callback_xruns.record(flags);
+            callback(Buffer::new(input, in_channels as usize), in_settings,
+                     BufferMut::new(output, out_channels as usize), out_settings,
+                     time, flags)
         });
 
         // Here we open the stream.
-        try!(stream.open(Some(&input_params), Some(&output_params), sample_hz, frames, flags, Some(f))
-                .map_err(|err| Error::PortAudio(err)));
+        let (input, output) = (Some(&input_params), Some(&output_params));
+        let result = host.backend().open_stream(input, output, sample_hz, frames, flags, Some(f));
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => return Err(format_error(err, host.backend(), input, output, sample_hz)),
+        };
+
+        let sample_hz = stream.sample_hz();
+        shared_sample_hz.set(sample_hz);
 
         // And now let's kick it off!
-        try!(stream.start().map_err(|err| Error::PortAudio(err)));
+        stream.start()?;
+
+        Ok(NonBlockingStream {
+            stream,
+            host,
+            input_params: StreamParams::negotiated(&input_params),
+            output_params: StreamParams::negotiated(&output_params),
+            input_settings: Settings::from_exact_sample_hz(sample_hz, frames, in_channels as u16),
+            output_settings: Settings::from_exact_sample_hz(sample_hz, frames, out_channels as u16),
+            frames_processed,
+            xruns,
+            diagnostics,
+            is_closed: false,
+        })
+    }
+
+    /// Launch a non-blocking duplex stream with a callback that receives and writes each buffer
+    /// in planar (non-interleaved) form, with each channel's samples stored contiguously.
+    ///
+    /// rust-portaudio only supports interleaved buffers, so the input is deinterleaved and the
+    /// output interleaved via buffers that are allocated up front. The output buffer is zeroed
+    /// before each call. With a variable number of frames per buffer, buffers larger than
+    /// `MINIMUM_BUFFER_RESERVATION` frames are passed to the callback in chunks.
+    pub fn run_planar_callback(self, mut callback: PlanarCallback<I, O>)
+        -> Result<NonBlockingStream<I, O, B>, Error>
+        where I: 'static,
+              O: 'static,
+    {
+        self.run_callback_with(move |in_settings, out_settings| {
+            let max_frames = planar_frames(in_settings);
+            let mut planar_input = vec![I::zero(); max_frames * in_settings.channels as usize];
+            let mut planar_output = vec![O::zero(); max_frames * out_settings.channels as usize];
+            Box::new(move |input: Buffer<I>, in_settings: Settings,
+                           mut output: BufferMut<O>, out_settings: Settings,
+                           time, flags| {
+                let in_channels = in_settings.channels as usize;
+                let out_channels = out_settings.channels as usize;
+                let mut written = 0;
+                let result = for_each_chunk(in_settings, time, max_frames, |chunk, settings, time| {
+                    let (in_settings, out_settings) =
+                        (settings, Settings { channels: out_settings.channels, ..settings });
+                    let planar_input = &mut planar_input[..chunk.len() * in_channels];
+                    let planar_output = &mut planar_output[..chunk.len() * out_channels];
+                    deinterleave(&input[chunk.start * in_channels..chunk.end * in_channels],
+                                 planar_input, in_channels);
+                    for sample in planar_output.iter_mut() {
+                        *sample = O::zero();
+                    }
+                    let result = callback(Planar::new(planar_input, in_channels), in_settings,
+                                          PlanarMut::new(planar_output, out_channels),
+                                          out_settings, time, flags);
+                    interleave(planar_output,
+                               &mut output[chunk.start * out_channels..chunk.end * out_channels],
+                               out_channels);
+                    written = chunk.end * out_channels;
+                    result
+                });
+                // Silence whatever follows the chunk at which the callback stopped.
+                for sample in output[written..].iter_mut() {
+                    *sample = O::zero();
+                }
+                result
+            })
+        })
+    }
 
-        Ok(NonBlockingStream { stream: stream, is_closed: false })
+    /// Launch a non-blocking duplex stream with the given callback along with a `Sender` for
+    /// passing messages to it.
+    ///
+    /// All messages that are pending at the start of each buffer are yielded by the `Drain`
+    /// given to the callback. The channel can hold up to `capacity` messages at once and is
+    /// wait-free on both ends, so no locks are ever taken on the audio thread.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    #[allow(clippy::type_complexity)]
+    pub fn run_callback_with_messages<M>(self,
+                                         capacity: usize,
+                                         mut callback: MessageCallback<I, O, M>)
+        -> Result<(NonBlockingStream<I, O, B>, Sender<M>), Error>
+        where I: 'static,
+              O: 'static,
+              M: Send + 'static,
+    {
+        let (sender, mut receiver) = spsc::channel(capacity);
+        let f = Box::new(move |input: Buffer<I>, in_settings: Settings,
+                               output: BufferMut<O>, out_settings: Settings,
+                               time, flags| {
+            callback(input, in_settings, output, out_settings, time, flags, receiver.drain())
+        });
+        self.run_callback(f).map(|stream| (stream, sender))
+    }
+
+    /// Launch a non-blocking duplex stream with the given callback along with a `Consumer` for
+    /// receiving the values that it pushes to its `Producer`.
+    ///
+    /// The channel can hold up to `capacity` values at once. Values pushed while it is full are
+    /// counted by `Consumer::overflows` rather than blocking the audio thread.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    #[allow(clippy::type_complexity)]
+    pub fn run_callback_with_producer<E>(self,
+                                         capacity: usize,
+                                         mut callback: ProducerCallback<I, O, E>)
+        -> Result<(NonBlockingStream<I, O, B>, Consumer<E>), Error>
+        where I: 'static,
+              O: 'static,
+              E: Send + 'static,
+    {
+        let (mut producer, consumer) = spsc::counting_channel(capacity);
+        let f = Box::new(move |input: Buffer<I>, in_settings: Settings,
+                               output: BufferMut<O>, out_settings: Settings,
+                               time, flags| {
+            callback(input, in_settings, output, out_settings, time, flags, &mut producer)
+        });
+        self.run_callback(f).map(|stream| (stream, consumer))
     }
 
     /// Launch a blocking duplex stream!
     #[inline]
-    pub fn run<'a>(self) -> Result<BlockingStream<'a, I, O>, Error>
+    pub fn run(self) -> Result<BlockingStream<I, O, B>, Error>
         where I: 'static,
               O: 'static,
     {
 
-        // Initialize PortAudio.
-        try!(pa::initialize().map_err(|err| Error::PortAudio(err)));
-
-        let (flags, input_params, output_params, sample_hz, frames) = try!(self.unwrap_params());
+        // Initialize the backend. The `Host` terminates it again should anything below fail.
+        self.stream_params.backend.initialize()?;
+        let params = self.unwrap_params();
+        let host = Host::initialized(self.stream_params.backend);
+        let diagnostics = self.stream_params.diagnostics;
 
-        // Here we construct our PortAudio stream.
-        let mut stream = pa::Stream::new();
+        let (flags, input_params, output_params, sample_hz, frames) = params?;
 
         // Here we open the stream.
-        try!(stream.open(Some(&input_params), Some(&output_params), sample_hz, frames, flags, None)
-                .map_err(|err| Error::PortAudio(err)));
+        let (input, output) = (Some(&input_params), Some(&output_params));
+        let result = host.backend()
+            .open_stream::<I, O>(input, output, sample_hz, frames, flags, None);
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => return Err(format_error(err, host.backend(), input, output, sample_hz)),
+        };
+
+        let sample_hz = stream.sample_hz();
 
         // And now let's kick it off!
-        try!(stream.start().map_err(|err| Error::PortAudio(err)));
+        stream.start()?;
 
         let in_channels = input_params.channel_count;
         let double_input_buffer_len = (frames as usize * in_channels as usize) * 2;
@@ -251,217 +487,405 @@ impl<I, O> Builder<I, O>
         let double_output_buffer_len = (frames as usize * out_channels as usize) * 2;
         let output_buffer_len = ::std::cmp::max(double_output_buffer_len, MINIMUM_BUFFER_RESERVATION);
 
+        let input_buffer = VecDeque::with_capacity(input_buffer_len);
+        let read_buffer = Vec::with_capacity(input_buffer.capacity());
+        // With a variable number of frames per buffer, an event may hold all buffered frames.
+        let event_buffer = Vec::with_capacity(input_buffer.capacity());
+        let output_buffer = VecDeque::with_capacity(output_buffer_len);
+        let user_buffer = Vec::with_capacity(output_buffer.capacity());
+        let write_buffer = Vec::with_capacity(output_buffer.capacity());
+
         Ok(BlockingStream {
-            stream: stream,
-            input_buffer: VecDeque::with_capacity(input_buffer_len),
-            output_buffer: VecDeque::with_capacity(output_buffer_len),
-            user_buffer: Vec::with_capacity(frames as usize * out_channels as usize),
-            frames: frames as u16,
+            stream,
+            host,
+            input_params: StreamParams::negotiated(&input_params),
+            output_params: StreamParams::negotiated(&output_params),
+            input_buffer,
+            read_buffer,
+            event_buffer,
+            output_buffer,
+            user_buffer,
+            write_buffer,
+            frames,
             in_channels: in_channels as u16,
             out_channels: out_channels as u16,
-            sample_hz: sample_hz as u32,
+            sample_hz,
+            in_frames_processed: 0,
+            out_frames_processed: 0,
+            xruns: XrunCounter::new(),
+            maybe_last_error: None,
+            diagnostics,
             last_event: None,
             is_closed: false,
-            marker: PhantomData,
         })
     }
 
 }
 
-impl<I, O> NonBlockingStream<I, O>
+impl<I, O, B> NonBlockingStream<I, O, B>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
 
-    /// Close the stream and terminate PortAudio.
+    /// Close the stream, terminating the backend if no other stream or `Host` is using it.
     pub fn close(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
         self.is_closed = true;
-        try!(self.stream.close().map_err(|err| Error::PortAudio(err)));
-        try!(pa::terminate().map_err(|err| Error::PortAudio(err)));
+        self.stream.close()?;
+        self.host.release()?;
         Ok(())
     }
 
+    /// The input parameters negotiated with the device, including the actual number of channels.
+    pub fn input_params(&self) -> &StreamParams<I> {
+        &self.input_params
+    }
+
+    /// The output parameters negotiated with the device, including the actual number of
+    /// channels.
+    pub fn output_params(&self) -> &StreamParams<O> {
+        &self.output_params
+    }
+
+    /// The settings with which the stream's input was opened, including the negotiated sample
+    /// rate. If a variable number of frames per buffer was requested, `frames` will be `0`.
+    pub fn input_settings(&self) -> Settings {
+        self.input_settings
+    }
+
+    /// The settings with which the stream's output was opened, including the negotiated sample
+    /// rate. If a variable number of frames per buffer was requested, `frames` will be `0`.
+    pub fn output_settings(&self) -> Settings {
+        self.output_settings
+    }
+
+    /// The number of frames processed by the callback since the stream was started.
+    ///
+    /// Unlike summing each `dt`, this count is exact and may be used for sample-accurate timing.
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed.get()
+    }
+
+    /// The number of each kind of under or overflow reported to the callback so far.
+    pub fn xrun_stats(&self) -> XrunStats {
+        self.xruns.stats()
+    }
+
     /// Check whether or not the stream is currently active.
     pub fn is_active(&self) -> Result<bool, Error> {
-        self.stream.is_active().map_err(|err| Error::PortAudio(err))
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.is_active()
+    }
+
+    /// Stop the stream once all pending buffers have been played, keeping the callback and the
+    /// device configuration so that it may later be resumed with `start`.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.stop()
+    }
+
+    /// Stop the stream immediately, discarding any pending buffers. The stream may later be
+    /// resumed with `start`.
+    pub fn abort(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.abort()
+    }
+
+    /// Resume a stream that was previously stopped or aborted.
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.start()
+    }
+
+    /// Check whether or not the stream is currently stopped.
+    pub fn is_stopped(&self) -> Result<bool, Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.is_stopped()
     }
 
 }
 
-impl<I, O> Drop for NonBlockingStream<I, O>
+impl<I, O, B> Drop for NonBlockingStream<I, O, B>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
     fn drop(&mut self) {
         if !self.is_closed {
             if let Err(err) = self.close() {
-                println!("An error occurred while closing NonBlockingStream: {}", err);
+                self.diagnostics.emit(Diagnostic::CloseFailed(err));
             }
         }
     }
 }
 
-impl<'a, I, O> BlockingStream<'a, I, O>
+impl<I, O, B> BlockingStream<I, O, B>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
-    /// Close the stream and terminate PortAudio.
+    /// Close the stream, terminating the backend if no other stream or `Host` is using it.
     pub fn close(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
         self.is_closed = true;
-        try!(self.stream.close().map_err(|err| Error::PortAudio(err)));
-        try!(pa::terminate().map_err(|err| Error::PortAudio(err)));
+        self.stream.close()?;
+        self.host.release()?;
         Ok(())
     }
+
+    /// The input parameters negotiated with the device, including the actual number of channels.
+    pub fn input_params(&self) -> &StreamParams<I> {
+        &self.input_params
+    }
+
+    /// The output parameters negotiated with the device, including the actual number of
+    /// channels.
+    pub fn output_params(&self) -> &StreamParams<O> {
+        &self.output_params
+    }
+
+    /// The settings with which the stream's input was opened, including the negotiated sample
+    /// rate.
+    ///
+    /// If a variable number of frames per buffer was requested, `frames` will be `0` and the
+    /// settings of each event hold the number of frames that it contains.
+    pub fn input_settings(&self) -> Settings {
+        Settings::from_exact_sample_hz(self.sample_hz, self.frames, self.in_channels)
+    }
+
+    /// The settings with which the stream's output was opened, including the negotiated sample
+    /// rate.
+    ///
+    /// If a variable number of frames per buffer was requested, `frames` will be `0` and the
+    /// settings of each event hold the number of frames that it contains.
+    pub fn output_settings(&self) -> Settings {
+        Settings::from_exact_sample_hz(self.sample_hz, self.frames, self.out_channels)
+    }
+
+    /// The number of frames that have been yielded by both `In` and `Out` events so far.
+    pub fn frames_processed(&self) -> u64 {
+        ::std::cmp::min(self.in_frames_processed, self.out_frames_processed)
+    }
+
+    /// The number of each kind of under or overflow that has occurred so far.
+    pub fn xrun_stats(&self) -> XrunStats {
+        self.xruns.stats()
+    }
+
 }
 
-impl<'a, I, O> Drop for BlockingStream<'a, I, O>
+impl<I, O, B> Drop for BlockingStream<I, O, B>
     where
         I: Sample + PaSample,
         O: Sample + PaSample,
+        B: Backend,
 {
     fn drop(&mut self) {
         if !self.is_closed {
             if let Err(err) = self.close() {
-                println!("An error occurred while closing BlockingStream: {}", err);
+                self.diagnostics.emit(Diagnostic::CloseFailed(err));
             }
         }
     }
 }
 
-impl<'a, I, O> Iterator for BlockingStream<'a, I, O>
+impl<I, O, B> BlockingStream<I, O, B>
     where
-        I: Sample + PaSample + 'a,
-        O: Sample + PaSample + 'a,
+        I: Sample + PaSample,
+        O: Sample + PaSample,
+        B: Backend,
 {
-    type Item = Event<'a, I, O>;
 
-    fn next(&mut self) -> Option<Event<'a, I, O>> {
+    /// Wait for the next event.
+    ///
+    /// Returns `None` should reading from or writing to the stream fail, in which case the error
+    /// is sent to the stream's diagnostics sink and may be retrieved via `last_error`.
+    pub fn next_event<'a>(&'a mut self) -> Option<Event<'a, I, O>> {
+        match self.wait_for_event() {
+            Ok(next) => Some(self.event(next)),
+            Err((operation, err)) => {
+                self.diagnostics.emit(Diagnostic::BlockingStreamEnded(operation, err.clone()));
+                self.maybe_last_error = Some(err);
+                None
+            },
+        }
+    }
+
+    /// Wait for the next event, returning the error should reading from or writing to the
+    /// stream fail.
+    ///
+    /// Unlike `next_event`, errors are returned rather than being sent to the stream's
+    /// diagnostics sink. The stream may be retried after an error.
+    pub fn try_next<'a>(&'a mut self) -> Result<Event<'a, I, O>, Error> {
+        let next = self.wait_for_event().map_err(|(_, err)| err)?;
+        Ok(self.event(next))
+    }
+
+    /// Call the given function with each event until it returns anything other than
+    /// `CallbackResult::Continue`, or until reading from or writing to the stream fails.
+    pub fn for_each_event<F>(&mut self, mut f: F) -> Result<(), Error>
+        where F: FnMut(Event<I, O>) -> CallbackResult,
+    {
+        loop {
+            let event = self.try_next()?;
+            if f(event) != CallbackResult::Continue {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The error that most recently caused `next_event` to return `None`, if any.
+    pub fn last_error(&self) -> Option<&Error> {
+        self.maybe_last_error.as_ref()
+    }
+
+    /// Produce the `Event` for the given `NextEvent`, lending the `event_buffer` for `In` events
+    /// and the `user_buffer` for `Out` events.
+    fn event<'a>(&'a mut self, next: NextEvent) -> Event<'a, I, O> {
+        match next {
+            NextEvent::In(settings, frame) => Event::In(&self.event_buffer[..], settings, frame),
+            NextEvent::Out(settings, frame) =>
+                Event::Out(&mut self.user_buffer[..], settings, frame),
+        }
+    }
+
+    /// Wait for the next event, returning the failed operation along with its error.
+    ///
+    /// The buffer for an `In` event is left in the `event_buffer`, while the buffer for an `Out`
+    /// event is left zeroed in the `user_buffer`.
+    fn wait_for_event(&mut self) -> Result<NextEvent, (StreamOperation, Error)> {
 
         let BlockingStream {
             ref mut stream,
+            ref xruns,
+            ref diagnostics,
             ref mut input_buffer,
+            ref mut read_buffer,
+            ref mut event_buffer,
             ref mut output_buffer,
             ref mut user_buffer,
+            ref mut write_buffer,
             ref mut last_event,
             ref frames,
             ref in_channels,
             ref out_channels,
             ref sample_hz,
+            ref mut in_frames_processed,
+            ref mut out_frames_processed,
             ..
         } = *self;
 
-        let input_settings = Settings { channels: *in_channels, frames: *frames, sample_hz: *sample_hz };
-        let target_input_buffer_size = input_settings.buffer_size();
-
-        let output_settings = Settings { channels: *out_channels, frames: *frames, sample_hz: *sample_hz };
-        let target_output_buffer_size = output_settings.buffer_size();
+        let input_settings = Settings::from_exact_sample_hz(*sample_hz, *frames, *in_channels);
 
         // If the user_buffer was written to last event, take it's contents and append them to the
         // output_buffer for writing.
         if let Some(LastEvent::Out) = *last_event {
             // If some frames were written last event, add them to our output_buffer.
-            if user_buffer.len() > 0 {
-                output_buffer.extend(user_buffer.iter().map(|&sample| sample));
+            if !user_buffer.is_empty() {
+                output_buffer.extend(user_buffer.iter().cloned());
                 user_buffer.clear();
             }
             // Considering the last event was an output event, let us check first for an input event.
-            if input_buffer.len() >= target_input_buffer_size {
-                let event_buffer = take_front(input_buffer, input_settings.buffer_size());
+            if let Some(settings) = take_input_event(input_buffer, event_buffer, input_settings) {
+                let frame = *in_frames_processed;
+                *in_frames_processed += settings.frames as u64;
                 *last_event = Some(LastEvent::In);
-                return Some(Event::In(event_buffer, input_settings));
+                return Ok(NextEvent::In(settings, frame));
             }
         }
 
         // Loop until we can satisfy an event condition.
         loop {
-            use std::error::Error as StdError;
 
             // How many frames are available on the input stream?
-            let available_in_frames = match wait_for_stream(|| stream.get_stream_read_available()) {
+            let available_in_frames =
+                wait_for_stream(|| stream.read_available(), xruns, diagnostics);
+            let available_in_frames = match available_in_frames {
                 Ok(frames) => frames,
-                Err(err) => {
-                    println!("An error occurred while requesting the number of available \
-                             frames for reading from the input stream: {}. BlockingStream will \
-                             now exit the event loop.", StdError::description(&err));
-                    return None;
-                },
+                Err(err) => return Err((StreamOperation::ReadAvailable, err)),
             };
 
-            // If there are frames available, let's take them and add them to our input_buffer.
-            if available_in_frames > 0 {
-                match stream.read(available_in_frames) {
-                    Ok(input_samples) => input_buffer.extend(input_samples.into_iter()),
-                    Err(err) => {
-                        println!("An error occurred while reading from the input stream: {}. \
-                                 BlockingStream will now exit the event loop.",
-                                 StdError::description(&err));
-                        return None;
-                    },
+            // If there are frames available and we have room in our input_buffer, let's take them.
+            let in_room = (input_buffer.capacity() - input_buffer.len()) / *in_channels as usize;
+            let read_frames = ::std::cmp::min(available_in_frames as usize, in_room);
+            if read_frames > 0 {
+                read_buffer.resize(read_frames * *in_channels as usize, I::zero());
+                if let Err(err) = stream.read(&mut read_buffer[..], read_frames as u32) {
+                    return Err((StreamOperation::Read, err));
                 }
+                input_buffer.extend(read_buffer.iter());
             }
 
             // How many frames are available for writing on the output stream?
-            let available_out_frames = match wait_for_stream(|| stream.get_stream_write_available()) {
+            let available_out_frames =
+                wait_for_stream(|| stream.write_available(), xruns, diagnostics);
+            let available_out_frames = match available_out_frames {
                 Ok(frames) => frames,
-                Err(err) => {
-                    println!("An error occurred while requesting the number of available \
-                             frames for writing from the output stream: {}. BlockingStream will \
-                             now exit the event loop.", StdError::description(&err));
-                    return None;
-                },
+                Err(err) => return Err((StreamOperation::WriteAvailable, err)),
             };
 
             // How many frames do we have in our output_buffer so far?
             let output_buffer_frames = (output_buffer.len() / *out_channels as usize) as u32;
 
             // If there are frames available for writing and we have some to write, then write!
+            let mut write_frames = 0;
             if available_out_frames > 0 && output_buffer_frames > 0 {
-                // If we have more than enough frames for writing, take them from the start of the buffer.
-                let (write_buffer, write_frames) = if output_buffer_frames >= available_out_frames {
-                    let out_samples = (available_out_frames * *out_channels as u32) as usize;
-                    let write_buffer = take_front(output_buffer, out_samples);
-                    (write_buffer, available_out_frames)
-                }
-                // Otherwise if we have less, just take what we can for now.
-                else {
-                    let len = output_buffer.len();
-                    let write_buffer = take_front(output_buffer, len);
-                    (write_buffer, output_buffer_frames)
-                };
-                if let Err(err) = stream.write(write_buffer, write_frames) {
-                    println!("An error occurred while writing to the output stream: {}. \
-                             BlockingStream will now exit the event loop.",
-                             StdError::description(&err));
-                    return None
+                // Take as many frames as may be written from the start of the buffer.
+                write_frames = ::std::cmp::min(available_out_frames, output_buffer_frames);
+                write_buffer.resize((write_frames * *out_channels as u32) as usize, O::zero());
+                drain_front(output_buffer, &mut write_buffer[..]);
+                if let Err(err) = stream.write(&write_buffer[..], write_frames) {
+                    return Err((StreamOperation::Write, err));
                 }
             }
 
+            // Ask for the requested number of frames, or for as many as the stream can currently
+            // take if the number may vary.
+            let out_room = output_buffer.capacity() - output_buffer.len();
+            let out_room = (out_room / *out_channels as usize) as u32;
+            let out_event_frames = match *frames {
+                0 => ::std::cmp::min(available_out_frames - write_frames, out_room),
+                frames => frames,
+            };
+
             // If we need more frames, return a buffer for writing.
-            if output_buffer.len() <= output_buffer.capacity() - target_output_buffer_size {
+            if out_event_frames > 0 && out_event_frames <= out_room {
                 use std::iter::repeat;
-                // Start the slice just after the already filled samples.
-                let start = user_buffer.len();
+                let output_settings =
+                    Settings::from_exact_sample_hz(*sample_hz, out_event_frames, *out_channels);
                 // Extend the update buffer by the necessary number of frames.
                 user_buffer.extend(repeat(O::zero()).take(output_settings.buffer_size()));
-                // Here we obtain a mutable reference to the slice with the correct lifetime so
-                // that we can return it via our `Event::Out`. Note: This means that a twisted,
-                // evil person could do horrific things with this iterator by calling `.next()`
-                // multiple times and storing aliasing mutable references to our output buffer,
-                // HOWEVER - this is extremely unlikely to occur in practise as the api is designed
-                // in a way that the reference is intended to die at the end of each loop before
-                // `.next()` even gets called again.
-                let slice = unsafe { ::std::mem::transmute(&mut user_buffer[start..]) };
+                let frame = *out_frames_processed;
+                *out_frames_processed += output_settings.frames as u64;
                 *last_event = Some(LastEvent::Out);
-                return Some(Event::Out(slice, output_settings));
+                return Ok(NextEvent::Out(output_settings, frame));
             }
             // Otherwise, if we've read enough frames for an In event, return one.
-            else if input_buffer.len() >= target_input_buffer_size {
-                let event_buffer = take_front(input_buffer, input_settings.buffer_size());
+            else if let Some(settings) =
+                take_input_event(input_buffer, event_buffer, input_settings)
+            {
+                let frame = *in_frames_processed;
+                *in_frames_processed += settings.frames as u64;
                 *last_event = Some(LastEvent::In);
-                return Some(Event::In(event_buffer, input_settings));
+                return Ok(NextEvent::In(settings, frame));
             }
 
             // If no events occured on this loop, set the last_event to None.
@@ -473,3 +897,26 @@ impl<'a, I, O> Iterator for BlockingStream<'a, I, O>
 
 }
 
+/// Move the frames for the next `In` event from the `input_buffer` into the `event_buffer`,
+/// returning the event's settings, or `None` if not enough frames have been read yet.
+///
+/// With a variable number of frames per buffer (`0`), the event holds all whole frames read so
+/// far.
+fn take_input_event<I>(input_buffer: &mut VecDeque<I>,
+                       event_buffer: &mut Vec<I>,
+                       settings: Settings) -> Option<Settings>
+    where I: Sample,
+{
+    let channels = settings.channels as usize;
+    let frames = match settings.frames {
+        0 => (input_buffer.len() / channels) as Frames,
+        frames => frames,
+    };
+    let settings = Settings { frames, ..settings };
+    if frames == 0 || input_buffer.len() < settings.buffer_size() {
+        return None;
+    }
+    event_buffer.resize(settings.buffer_size(), I::zero());
+    drain_front(input_buffer, &mut event_buffer[..]);
+    Some(settings)
+}
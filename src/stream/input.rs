@@ -1,105 +1,193 @@
 
+use backend::{Backend, Host, PortAudio, RawStream};
+use buffer::Buffer;
+use diagnostics::{Diagnostic, Diagnostics, StreamOperation};
 use error::Error;
+use format::StreamFormat;
+use planar::Planar;
+#[cfg(feature="futures")]
+use futures_core::Stream;
 use portaudio::pa;
 use portaudio::pa::Sample as PaSample;
 use sample::{Sample, Wave};
-use settings::{Channels, Settings, Frames, SampleHz};
+use settings::{Channels, Settings, Frames};
+use spsc::{self, Consumer, Drain, Producer, Sender};
 use std::collections::VecDeque;
+#[cfg(feature="futures")]
+use std::pin::Pin;
+#[cfg(feature="futures")]
+use std::task::{Context, Poll};
+use utils::{deinterleave, drain_front};
 
 use super::{
     BufferFrequency,
     CallbackFlags,
     CallbackResult,
-    DeltaTimeSeconds,
+    Direction,
+    FramesProcessed,
     MINIMUM_BUFFER_RESERVATION,
     PaParams,
+    SharedSampleHz,
     SoundStream,
     StreamFlags,
     StreamParams,
+    StreamTime,
+    Timer,
+    XrunCounter,
+    XrunStats,
+    check_format,
+    for_each_chunk,
+    format_error,
+    planar_frames,
     wait_for_stream,
 };
+#[cfg(feature="futures")]
+use super::{Notifier, Wakeup, buffer_len, wakeup};
 
 
 /// A builder context for an Input sound stream.
-pub struct Builder<I> {
-    pub stream_params: SoundStream,
+pub struct Builder<I, B=PortAudio> {
+    pub stream_params: SoundStream<B>,
     pub input_params: StreamParams<I>,
 }
 
-/// An iterator of blocking input stream events.
-pub struct BlockingStream<I=Wave> where I: Sample + PaSample {
+/// A blocking input stream, yielding each buffer of input via `next_buffer`.
+///
+/// All buffers are allocated up front and reused for the lifetime of the stream.
+pub struct BlockingStream<I=Wave, B=PortAudio> where I: Sample + PaSample, B: Backend {
     /// Buffer the samples from the input until its length is equal to the buffer_length.
     buffer: VecDeque<I>,
+    /// The samples most recently read from the backend.
+    read_buffer: Vec<I>,
+    /// The buffer lent to the user by each event.
+    event_buffer: Vec<I>,
     /// Number of input channels.
     channels: Channels,
-    /// Stream sample rate.
-    sample_hz: SampleHz,
+    /// The exact stream sample rate.
+    sample_hz: f64,
     /// Frames per buffer.
     frames: Frames,
-    /// The port audio stream.
-    stream: pa::Stream<I, I>,
+    /// The number of frames yielded by events or read so far.
+    frames_processed: u64,
+    /// Where diagnostics are sent.
+    diagnostics: Diagnostics,
+    /// Counts the xruns reported while waiting on the stream.
+    xruns: XrunCounter,
+    /// The error that most recently ended iteration.
+    maybe_last_error: Option<Error>,
+    /// The backend's stream.
+    stream: Box<RawStream<I, I>>,
+    /// Keeps the backend initialized while the stream is open.
+    host: Host<B>,
+    /// The parameters negotiated with the device.
+    params: StreamParams<I>,
     is_closed: bool,
 }
 
 /// Stream callback function type.
 pub type Callback<I> =
-    Box<FnMut(&[I], Settings, DeltaTimeSeconds, CallbackFlags) -> CallbackResult>;
+    Box<FnMut(Buffer<I>, Settings, StreamTime, CallbackFlags) -> CallbackResult>;
+
+/// Stream callback function type that receives each buffer in planar form.
+pub type PlanarCallback<I> =
+    Box<FnMut(Planar<I>, Settings, StreamTime, CallbackFlags) -> CallbackResult>;
+
+/// Stream callback function type that also receives the messages sent to the stream.
+pub type MessageCallback<I, M> =
+    Box<FnMut(Buffer<I>, Settings, StreamTime, CallbackFlags, Drain<M>) -> CallbackResult>;
+
+/// Stream callback function type that may also push values out of the audio thread.
+pub type ProducerCallback<I, E> =
+    Box<FnMut(Buffer<I>, Settings, StreamTime, CallbackFlags, &mut Producer<E>) -> CallbackResult>;
 
 /// A handle to the non-blocking input stream.
-pub struct NonBlockingStream<I=Wave> where I: Sample + PaSample {
-    /// The port audio stream.
-    stream: pa::Stream<I, I>,
+pub struct NonBlockingStream<I=Wave, B=PortAudio> where I: Sample + PaSample, B: Backend {
+    /// The backend's stream.
+    stream: Box<RawStream<I, I>>,
+    /// Keeps the backend initialized while the stream is open.
+    host: Host<B>,
+    /// The parameters negotiated with the device.
+    params: StreamParams<I>,
+    /// The settings with which the stream was opened.
+    settings: Settings,
+    /// The number of frames processed by the callback.
+    frames_processed: FramesProcessed,
+    /// Where diagnostics are sent.
+    diagnostics: Diagnostics,
+    /// Counts the xruns reported to the callback.
+    xruns: XrunCounter,
+    /// Marks the stream's async adapter, if any, as closed once the stream is closed.
+    #[cfg(feature="futures")]
+    maybe_notifier: Option<Notifier>,
     /// Is the stream currently closed.
     is_closed: bool,
 }
 
 /// An event returned by the Blocking Stream.
+///
+/// Holds the input samples, the stream's settings and the index of the buffer's first frame.
 #[derive(Clone, Debug)]
-pub struct Event<I>(pub Vec<I>, pub Settings);
+pub struct Event<'a, I: 'a>(pub &'a [I], pub Settings, pub u64);
 
-impl<I> Builder<I> where I: Sample + PaSample {
+/// A buffer of input yielded by a `BufferStream`.
+///
+/// Holds the input samples, the stream's settings and the timing of the buffer.
+#[cfg(feature="futures")]
+#[derive(Clone, Debug)]
+pub struct AsyncEvent<I>(pub Vec<I>, pub Settings, pub StreamTime);
+
+/// A `futures::Stream` of the buffers read by a non-blocking input stream's callback.
+///
+/// The stream ends once the `NonBlockingStream` is closed or dropped.
+#[cfg(feature="futures")]
+pub struct BufferStream<I> {
+    /// The buffers pushed by the callback.
+    events: Consumer<AsyncEvent<I>>,
+    /// Buffers handed back for the callback to reuse.
+    recycled: Sender<Vec<I>>,
+    /// Woken by the callback whenever it pushes a buffer.
+    wakeup: Wakeup,
+}
+
+impl<I, B> Builder<I, B> where I: Sample + PaSample, B: Backend {
 
     /// Retrieve the flags, input stream parameters, sample rate and frames per buffer.
-    fn unwrap_params(self) -> Result<PaParams, Error> {
-        let Builder { stream_params, input_params } = self;
-        let SoundStream { maybe_buffer_frequency, maybe_sample_hz, maybe_flags } = stream_params;
+    fn unwrap_params(&self) -> Result<PaParams, Error> {
+        let Builder { ref stream_params, ref input_params } = *self;
+        let SoundStream {
+            maybe_buffer_frequency, maybe_flags, strict_channels, ref backend, ..
+        } = *stream_params;
+
+        // Check the requested values before querying any devices.
+        stream_params.validate()?;
+        input_params.validate()?;
 
         // Retrieve any stream flags.
-        let flags = maybe_flags.unwrap_or_else(|| StreamFlags::empty());
+        let flags = maybe_flags.unwrap_or_else(StreamFlags::empty);
 
         // Construct the PortAudio input params from the sound stream ones.
         let input_params = {
-            let idx = input_params.idx.unwrap_or_else(|| pa::device::get_default_input());
-            let info = match pa::device::get_info(idx) {
-                Ok(info) => info,
-                Err(err) => return Err(Error::PortAudio(err)),
-            };
-            let channels = input_params.channel_count
-                .map(|n| ::std::cmp::min(n, info.max_input_channels))
-                .unwrap_or_else(|| ::std::cmp::min(2, info.max_input_channels));
+            let idx = input_params.resolve_device(backend, Direction::Input)?;
+            let info = backend.device_info(idx)?;
+            let channels = input_params.negotiate_channels(&info, Direction::Input,
+                                                           strict_channels)?;
             let sample_format = input_params.sample_format();
             let suggested_latency = input_params.suggested_latency
-                .unwrap_or_else(|| info.default_low_input_latency);
+                .unwrap_or(info.default_low_input_latency);
             pa::StreamParameters {
                 device: idx,
                 channel_count: channels,
-                sample_format: sample_format,
-                suggested_latency: suggested_latency,
+                sample_format,
+                suggested_latency,
             }
         };
 
         // Determine the sample rate.
-        let sample_hz = match maybe_sample_hz {
-            Some(sample_hz) => sample_hz,
-            None => match pa::device::get_info(input_params.device) {
-                Ok(info) => info.default_sample_rate,
-                Err(err) => return Err(Error::PortAudio(err)),
-            },
-        };
+        let sample_hz = stream_params.negotiate_sample_hz(Some(&input_params), None)?;
 
         // Determine the closest number of frames per buffer to the requested rate.
         let frames = match maybe_buffer_frequency {
-            Some(BufferFrequency::Frames(frames)) => frames as u32,
+            Some(BufferFrequency::Frames(frames)) => frames,
             Some(BufferFrequency::Hz(hz)) => (sample_hz as f32 / hz).round() as u32,
             None => 0,
         };
@@ -107,192 +195,663 @@ impl<I> Builder<I> where I: Sample + PaSample {
         Ok((flags, input_params, sample_hz, frames))
     }
 
+    /// Check whether the device, channels, sample format and sample rate are supported by the
+    /// backend without starting any audio.
+    ///
+    /// Returns the format that would be requested when the stream is run, or an
+    /// `UnsupportedFormat` error describing it. Note that the number of channels is clamped to
+    /// the device's maximum as it would be when run, unless `strict_channels` was requested.
+    pub fn check(&self) -> Result<StreamFormat, Error> {
+        let backend = &self.stream_params.backend;
+        backend.initialize()?;
+        let result = self.unwrap_params().and_then(|(_, input_params, sample_hz, _)| {
+            check_format(backend, Some(&input_params), None, sample_hz)
+        });
+        backend.terminate()?;
+        result
+    }
+
+    /// Whether or not the stream's format is supported. See `check` for details.
+    pub fn is_supported(&self) -> bool {
+        self.check().is_ok()
+    }
+
     /// Launch a non-blocking input stream with the given callback!
     #[inline]
-    pub fn run_callback(self, mut callback: Callback<I>) -> Result<NonBlockingStream<I>, Error>
+    pub fn run_callback(self, callback: Callback<I>) -> Result<NonBlockingStream<I, B>, Error>
+        where I: 'static,
+    {
+        self.run_callback_with(move |_| callback)
+    }
+
+    /// Launch a non-blocking input stream with the callback returned by `new_callback`.
+    ///
+    /// `new_callback` is given the negotiated settings before the stream is opened, so that any
+    /// buffers used by the callback may be allocated up front.
+    fn run_callback_with<F>(self, new_callback: F) -> Result<NonBlockingStream<I, B>, Error>
         where I: 'static,
+              F: FnOnce(Settings) -> Callback<I>,
     {
 
-        // Initialize PortAudio.
-        try!(pa::initialize().map_err(|err| Error::PortAudio(err)));
+        // Initialize the backend. The `Host` terminates it again should anything below fail.
+        self.stream_params.backend.initialize()?;
+        let params = self.unwrap_params();
+        let host = Host::initialized(self.stream_params.backend);
+        let diagnostics = self.stream_params.diagnostics;
 
-        let (flags, input_params, sample_hz, frames) = try!(self.unwrap_params());
+        let (flags, input_params, sample_hz, frames) = params?;
         let channels = input_params.channel_count;
+        let mut callback =
+            new_callback(Settings::from_exact_sample_hz(sample_hz, frames, channels as u16));
 
-        // Here we construct our PortAudio stream.
-        let mut stream = pa::Stream::new();
+        // The callback learns the actual sample rate once the stream has been opened.
+        let shared_sample_hz = SharedSampleHz::new(sample_hz);
+        let callback_sample_hz = shared_sample_hz.clone();
 
-        // Remember the last time the callback was called so we can create the delta time.
-        let mut maybe_last_time = None; 
+        // Tracks the time and position of each buffer.
+        let mut timer = Timer::new();
+        let frames_processed = timer.frames_processed();
+
+        // Count the xruns reported to the callback.
+        let xruns = XrunCounter::new();
+        let callback_xruns = xruns.clone();
 
         // Construct a wrapper function around our callback.
         let f = Box::new(move |input: &[I],
                                _output: &mut[I],
                                frames: u32,
                                time_info: &pa::StreamCallbackTimeInfo,
-                               flags: pa::StreamCallbackFlags| -> pa::StreamCallbackResult
+                               flags: CallbackFlags| -> CallbackResult
         {
-            let settings = Settings {
-                sample_hz: sample_hz as u32,
-                frames: frames as u16,
-                channels: channels as u16,
-            };
-            let dt = time_info.current_time - maybe_last_time.unwrap_or(time_info.current_time);
-            maybe_last_time = Some(time_info.current_time);
-            match callback(input, settings, dt, flags) {
-                CallbackResult::Continue => pa::StreamCallbackResult::Continue,
-                CallbackResult::Complete => pa::StreamCallbackResult::Complete,
-                CallbackResult::Abort    => pa::StreamCallbackResult::Abort,
-            }
+            let sample_hz = callback_sample_hz.get();
+            let settings = Settings::from_exact_sample_hz(sample_hz, frames, channels as u16);
+            let time = timer.next(time_info, frames);
+            callback_xruns.record(flags);
+            callback(Buffer::new(input, channels as usize), settings, time, flags)
         });
 
         // Here we open the stream.
-        try!(stream.open(Some(&input_params), None, sample_hz, frames, flags, Some(f))
-            .map_err(|err| Error::PortAudio(err)));
+        let (input, output) = (Some(&input_params), None);
+        let result = host.backend().open_stream(input, output, sample_hz, frames, flags, Some(f));
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => return Err(format_error(err, host.backend(), input, output, sample_hz)),
+        };
+
+        let sample_hz = stream.sample_hz();
+        shared_sample_hz.set(sample_hz);
 
         // And now let's kick it off!
-        try!(stream.start().map_err(|err| Error::PortAudio(err)));
+        stream.start()?;
+
+        Ok(NonBlockingStream {
+            stream,
+            host,
+            params: StreamParams::negotiated(&input_params),
+            settings: Settings::from_exact_sample_hz(sample_hz, frames, channels as u16),
+            frames_processed,
+            xruns,
+            diagnostics,
+            #[cfg(feature="futures")]
+            maybe_notifier: None,
+            is_closed: false,
+        })
+    }
+
+    /// Launch a non-blocking input stream with a callback that receives each buffer in planar
+    /// (non-interleaved) form, with each channel's samples stored contiguously.
+    ///
+    /// rust-portaudio only delivers interleaved buffers, so each buffer is deinterleaved into a
+    /// buffer that is allocated up front. With a variable number of frames per buffer, buffers
+    /// larger than `MINIMUM_BUFFER_RESERVATION` frames are passed to the callback in chunks.
+    pub fn run_planar_callback(self, mut callback: PlanarCallback<I>)
+        -> Result<NonBlockingStream<I, B>, Error>
+        where I: 'static,
+    {
+        self.run_callback_with(move |settings| {
+            let max_frames = planar_frames(settings);
+            let mut planar = vec![I::zero(); max_frames * settings.channels as usize];
+            Box::new(move |input: Buffer<I>, settings: Settings, time, flags| {
+                let channels = settings.channels as usize;
+                for_each_chunk(settings, time, max_frames, |frames, settings, time| {
+                    let planar = &mut planar[..frames.len() * channels];
+                    deinterleave(&input[frames.start * channels..frames.end * channels],
+                                 planar, channels);
+                    callback(Planar::new(planar, channels), settings, time, flags)
+                })
+            })
+        })
+    }
+
+    /// Launch a non-blocking input stream with the given callback along with a `Sender` for
+    /// passing messages to it.
+    ///
+    /// All messages that are pending at the start of each buffer are yielded by the `Drain`
+    /// given to the callback. The channel can hold up to `capacity` messages at once and is
+    /// wait-free on both ends, so no locks are ever taken on the audio thread.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    pub fn run_callback_with_messages<M>(self, capacity: usize, mut callback: MessageCallback<I, M>)
+        -> Result<(NonBlockingStream<I, B>, Sender<M>), Error>
+        where I: 'static,
+              M: Send + 'static,
+    {
+        let (sender, mut receiver) = spsc::channel(capacity);
+        let f = Box::new(move |input: Buffer<I>, settings: Settings, time, flags| {
+            callback(input, settings, time, flags, receiver.drain())
+        });
+        self.run_callback(f).map(|stream| (stream, sender))
+    }
 
-        Ok(NonBlockingStream { stream: stream, is_closed: false })
+    /// Launch a non-blocking input stream with the given callback along with a `Consumer` for
+    /// receiving the values that it pushes to its `Producer`.
+    ///
+    /// The channel can hold up to `capacity` values at once. Values pushed while it is full are
+    /// counted by `Consumer::overflows` rather than blocking the audio thread.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    pub fn run_callback_with_producer<E>(self,
+                                         capacity: usize,
+                                         mut callback: ProducerCallback<I, E>)
+        -> Result<(NonBlockingStream<I, B>, Consumer<E>), Error>
+        where I: 'static,
+              E: Send + 'static,
+    {
+        let (mut producer, consumer) = spsc::counting_channel(capacity);
+        let f = Box::new(move |input: Buffer<I>, settings: Settings, time, flags| {
+            callback(input, settings, time, flags, &mut producer)
+        });
+        self.run_callback(f).map(|stream| (stream, consumer))
+    }
+
+    /// Launch a non-blocking input stream along with a `futures::Stream` of its buffers.
+    ///
+    /// Up to `capacity` buffers may be waiting to be polled at once. The audio callback never
+    /// blocks, so buffers read while the queue is full are dropped and counted by
+    /// `BufferStream::overflows`. The polling task is woken from the callback as each buffer
+    /// arrives.
+    ///
+    /// `capacity` buffers are allocated up front and the callback never allocates, so each
+    /// buffer should be handed back with `BufferStream::recycle` once it has been used. Input
+    /// that arrives while no buffer is available is likewise dropped and counted.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    #[cfg(feature="futures")]
+    pub fn run_stream(self, capacity: usize)
+        -> Result<(NonBlockingStream<I, B>, BufferStream<I>), Error>
+        where I: Send + 'static,
+    {
+        let (mut producer, events) = spsc::counting_channel(capacity);
+        let (mut recycled, mut recycled_receiver) = spsc::channel(capacity);
+        let (notifier, wakeup) = wakeup();
+        let closer = wakeup.notifier();
+        let mut spare = None;
+        let result = self.run_callback_with(|settings| {
+            // Fill the pool up front so that the callback never has to allocate.
+            for _ in 0..capacity {
+                let _ = recycled.try_send(Vec::with_capacity(buffer_len(settings)));
+            }
+            Box::new(move |input: Buffer<I>, settings: Settings, time, _flags| {
+                let mut buffer = match spare.take().or_else(|| recycled_receiver.try_recv()) {
+                    Some(buffer) => buffer,
+                    // Drop the input rather than allocating on the audio thread.
+                    None => {
+                        producer.count_overflow();
+                        return CallbackResult::Continue;
+                    },
+                };
+                buffer.clear();
+                buffer.extend_from_slice(&input);
+                match producer.push(AsyncEvent(buffer, settings, time)) {
+                    Ok(()) => notifier.notify(),
+                    // Keep the buffer for the next callback rather than freeing it on this thread.
+                    Err(AsyncEvent(buffer, _, _)) => spare = Some(buffer),
+                }
+                CallbackResult::Continue
+            })
+        });
+        let buffers = BufferStream { events, recycled, wakeup };
+        result.map(|mut stream| {
+            stream.maybe_notifier = Some(closer);
+            (stream, buffers)
+        })
     }
 
     /// Launch a blocking input stream!
     #[inline]
-    pub fn run(self) -> Result<BlockingStream<I>, Error>
+    pub fn run(self) -> Result<BlockingStream<I, B>, Error>
         where I: 'static,
     {
 
-        // Initialize PortAudio.
-        try!(pa::initialize().map_err(|err| Error::PortAudio(err)));
-
-        let (flags, input_params, sample_hz, frames) = try!(self.unwrap_params());
+        // Initialize the backend. The `Host` terminates it again should anything below fail.
+        self.stream_params.backend.initialize()?;
+        let params = self.unwrap_params();
+        let host = Host::initialized(self.stream_params.backend);
+        let diagnostics = self.stream_params.diagnostics;
 
-        // Here we construct our PortAudio stream.
-        let mut stream = pa::Stream::new();
+        let (flags, input_params, sample_hz, frames) = params?;
 
         // Here we open the stream.
-        try!(stream.open(Some(&input_params), None, sample_hz, frames, flags, None)
-            .map_err(|err| Error::PortAudio(err)));
+        let (input, output) = (Some(&input_params), None);
+        let result = host.backend()
+            .open_stream::<I, I>(input, output, sample_hz, frames, flags, None);
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => return Err(format_error(err, host.backend(), input, output, sample_hz)),
+        };
+
+        let sample_hz = stream.sample_hz();
 
         // And now let's kick it off!
-        try!(stream.start().map_err(|err| Error::PortAudio(err)));
+        stream.start()?;
 
         let channels = input_params.channel_count;
         let double_buffer_len = (frames as usize * channels as usize) * 2;
         let buffer_len = ::std::cmp::max(double_buffer_len, MINIMUM_BUFFER_RESERVATION);
 
+        let buffer = VecDeque::with_capacity(buffer_len);
+        let read_buffer = Vec::with_capacity(buffer.capacity());
+        // With a variable number of frames per buffer, an event may hold all buffered frames.
+        let event_buffer = Vec::with_capacity(buffer.capacity());
+
         Ok(BlockingStream {
-            buffer: VecDeque::with_capacity(buffer_len),
-            stream: stream,
+            buffer,
+            read_buffer,
+            event_buffer,
+            stream,
+            host,
+            params: StreamParams::negotiated(&input_params),
             channels: channels as u16,
-            frames: frames as u16,
-            sample_hz: sample_hz as u32,
+            frames,
+            sample_hz,
+            frames_processed: 0,
+            xruns: XrunCounter::new(),
+            maybe_last_error: None,
+            diagnostics,
             is_closed: false,
         })
     }
 
 }
 
-impl<I> NonBlockingStream<I> where I: Sample + PaSample {
+impl<I, B> NonBlockingStream<I, B> where I: Sample + PaSample, B: Backend {
 
-    /// Close the stream and terminate PortAudio.
+    /// Close the stream, terminating the backend if no other stream or `Host` is using it.
     pub fn close(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
         self.is_closed = true;
-        try!(self.stream.close().map_err(|err| Error::PortAudio(err)));
-        try!(pa::terminate().map_err(|err| Error::PortAudio(err)));
+        // The backend may keep the callback and its `Notifier` alive until the stream is
+        // dropped, so end any async adapter now.
+        #[cfg(feature="futures")]
+        let _ = self.maybe_notifier.take();
+        self.stream.close()?;
+        self.host.release()?;
         Ok(())
     }
 
+    /// The parameters negotiated with the device, including the actual number of channels.
+    pub fn params(&self) -> &StreamParams<I> {
+        &self.params
+    }
+
+    /// The settings with which the stream was opened, including the negotiated sample rate.
+    ///
+    /// If a variable number of frames per buffer was requested, `frames` will be `0`.
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// The number of frames processed by the callback since the stream was started.
+    ///
+    /// Unlike summing each `dt`, this count is exact and may be used for sample-accurate timing.
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed.get()
+    }
+
+    /// The number of each kind of under or overflow reported to the callback so far.
+    pub fn xrun_stats(&self) -> XrunStats {
+        self.xruns.stats()
+    }
+
     /// Check whether or not the stream is currently active.
     pub fn is_active(&self) -> Result<bool, Error> {
-        self.stream.is_active().map_err(|err| Error::PortAudio(err))
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.is_active()
+    }
+
+    /// Stop the stream once all pending buffers have been played, keeping the callback and the
+    /// device configuration so that it may later be resumed with `start`.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.stop()
+    }
+
+    /// Stop the stream immediately, discarding any pending buffers. The stream may later be
+    /// resumed with `start`.
+    pub fn abort(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.abort()
+    }
+
+    /// Resume a stream that was previously stopped or aborted.
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.start()
+    }
+
+    /// Check whether or not the stream is currently stopped.
+    pub fn is_stopped(&self) -> Result<bool, Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.is_stopped()
     }
 
 }
 
-impl<I> Drop for NonBlockingStream<I> where I: Sample + PaSample {
+impl<I, B> Drop for NonBlockingStream<I, B> where I: Sample + PaSample, B: Backend {
     fn drop(&mut self) {
         if !self.is_closed {
             if let Err(err) = self.close() {
-                println!("An error occurred while closing NonBlockingStream: {}", err);
+                self.diagnostics.emit(Diagnostic::CloseFailed(err));
             }
         }
     }
 }
 
-impl<I> BlockingStream<I> where I: Sample + PaSample {
-    /// Close the stream and terminate PortAudio.
+impl<I, B> BlockingStream<I, B> where I: Sample + PaSample, B: Backend {
+    /// Close the stream, terminating the backend if no other stream or `Host` is using it.
     pub fn close(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
         self.is_closed = true;
-        try!(self.stream.close().map_err(|err| Error::PortAudio(err)));
-        try!(pa::terminate().map_err(|err| Error::PortAudio(err)));
+        self.stream.close()?;
+        self.host.release()?;
         Ok(())
     }
+
+    /// The parameters negotiated with the device, including the actual number of channels.
+    pub fn params(&self) -> &StreamParams<I> {
+        &self.params
+    }
+
+    /// The settings with which the stream was opened, including the negotiated sample rate.
+    ///
+    /// If a variable number of frames per buffer was requested, `frames` will be `0` and the
+    /// settings of each event hold the number of frames that it contains.
+    pub fn settings(&self) -> Settings {
+        Settings::from_exact_sample_hz(self.sample_hz, self.frames, self.channels)
+    }
+
+    /// The number of frames yielded by the stream's events or read via `read_frames` so far.
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed
+    }
+
+    /// The number of each kind of under or overflow that has occurred so far.
+    pub fn xrun_stats(&self) -> XrunStats {
+        self.xruns.stats()
+    }
+
+    /// Read interleaved frames into the given buffer, blocking until at least one frame is
+    /// available.
+    ///
+    /// Reads as many whole frames as are available and fit within the buffer, returning the
+    /// number of frames read. Only the first `frames * channels` samples of the buffer are written.
+    /// Returns `0` only if the buffer is too short to hold a single frame.
+    ///
+    /// While the stream reports that no frames are available, this spins, polling it again
+    /// without sleeping.
+    pub fn read_frames(&mut self, buffer: &mut [I]) -> Result<usize, Error> {
+        let channels = self.channels as usize;
+        let max_frames = buffer.len() / channels;
+        if max_frames == 0 {
+            return Ok(0);
+        }
+
+        // Wait for at least one frame, unless some remain from a previous read or event.
+        while self.buffer.len() < channels {
+            self.read_available_frames().map_err(|(_, err)| err)?;
+        }
+
+        let frames = ::std::cmp::min(max_frames, self.buffer.len() / channels);
+        drain_front(&mut self.buffer, &mut buffer[..frames * channels]);
+        self.frames_processed += frames as u64;
+        Ok(frames)
+    }
+
 }
 
-impl<I> Drop for BlockingStream<I> where I: Sample + PaSample {
+impl<I, B> Drop for BlockingStream<I, B> where I: Sample + PaSample, B: Backend {
     fn drop(&mut self) {
         if !self.is_closed {
             if let Err(err) = self.close() {
-                println!("An error occurred while closing BlockingStream: {}", err);
+                self.diagnostics.emit(Diagnostic::CloseFailed(err));
             }
         }
     }
 }
 
-impl<I> Iterator for BlockingStream<I> where I: Sample + PaSample {
-    type Item = Event<I>;
+impl<I, B> BlockingStream<I, B> where I: Sample + PaSample, B: Backend {
+
+    /// Wait for the next buffer of input.
+    ///
+    /// Returns `None` should reading from the stream fail, in which case the error is sent to the
+    /// stream's diagnostics sink and may be retrieved via `last_error`.
+    pub fn next_buffer<'a>(&'a mut self) -> Option<Event<'a, I>> {
+        match self.next_event() {
+            Ok((settings, frame)) => Some(Event(&self.event_buffer[..], settings, frame)),
+            Err((operation, err)) => {
+                self.diagnostics.emit(Diagnostic::BlockingStreamEnded(operation, err.clone()));
+                self.maybe_last_error = Some(err);
+                None
+            },
+        }
+    }
+
+    /// Wait for the next buffer of input, returning the error should reading from the stream
+    /// fail.
+    ///
+    /// Unlike `next_buffer`, errors are returned rather than being sent to the stream's
+    /// diagnostics sink. The stream may be retried after an error.
+    pub fn try_next<'a>(&'a mut self) -> Result<Event<'a, I>, Error> {
+        let (settings, frame) = self.next_event().map_err(|(_, err)| err)?;
+        Ok(Event(&self.event_buffer[..], settings, frame))
+    }
 
-    fn next(&mut self) -> Option<Event<I>> {
+    /// Call the given function with each buffer of input until it returns anything other than
+    /// `CallbackResult::Continue`, or until reading from the stream fails.
+    pub fn for_each_buffer<F>(&mut self, mut f: F) -> Result<(), Error>
+        where F: FnMut(Event<I>) -> CallbackResult,
+    {
+        loop {
+            let event = self.try_next()?;
+            if f(event) != CallbackResult::Continue {
+                return Ok(());
+            }
+        }
+    }
+
+    /// The error that most recently caused `next_buffer` to return `None`, if any.
+    pub fn last_error(&self) -> Option<&Error> {
+        self.maybe_last_error.as_ref()
+    }
+
+    /// Wait until the next buffer of input has been copied into the `event_buffer`, returning its
+    /// settings and the index of its first frame, or the failed operation along with its error.
+    ///
+    /// With a variable number of frames per buffer, each event holds all frames read so far.
+    fn next_event(&mut self) -> Result<(Settings, u64), (StreamOperation, Error)> {
+        let channels = self.channels as usize;
+
+        // Wait until we have the requested number of frames, or at least one if it may vary.
+        let min_frames = ::std::cmp::max(self.frames as usize, 1);
+        while self.buffer.len() < min_frames * channels {
+            self.read_available_frames()?;
+        }
+
+        let frames = match self.frames {
+            0 => self.buffer.len() / channels,
+            frames => frames as usize,
+        };
+        let settings = Settings { frames: frames as Frames, ..self.settings() };
+        let buffer_size = settings.buffer_size();
+        self.event_buffer.resize(buffer_size, I::zero());
+        drain_front(&mut self.buffer, &mut self.event_buffer[..]);
+        let frame = self.frames_processed;
+        self.frames_processed += settings.frames as u64;
+        Ok((settings, frame))
+    }
 
+    /// Wait for input, then read as many of the available frames as there is room for in the
+    /// `buffer` so that it never reallocates.
+    fn read_available_frames(&mut self) -> Result<(), (StreamOperation, Error)> {
         let BlockingStream {
             ref mut buffer,
+            ref mut read_buffer,
             ref mut stream,
-            ref channels,
-            ref frames,
-            ref sample_hz,
+            ref xruns,
+            ref diagnostics,
+            channels,
             ..
         } = *self;
+        let channels = channels as usize;
 
-        let settings = Settings { channels: *channels, frames: *frames, sample_hz: *sample_hz };
-        let buffer_size = settings.buffer_size();
-
-        loop {
-            use std::error::Error as StdError;
-            use utils::take_front;
+        // How many frames are available on the input stream?
+        let available_frames = match wait_for_stream(|| stream.read_available(), xruns, diagnostics) {
+            Ok(frames) => frames as usize,
+            Err(err) => return Err((StreamOperation::ReadAvailable, err)),
+        };
 
-            // If we have the requested number of frames, return them in an Event.
-            if buffer.len() >= buffer_size {
-                let event_buffer = take_front(buffer, buffer_size);
-                return Some(Event(event_buffer, settings));
+        // If there are frames available and we have room in the buffer, take them.
+        let room = (buffer.capacity() - buffer.len()) / channels;
+        let frames = ::std::cmp::min(available_frames, room);
+        if frames > 0 {
+            read_buffer.resize(frames * channels, I::zero());
+            if let Err(err) = stream.read(&mut read_buffer[..], frames as u32) {
+                return Err((StreamOperation::Read, err));
             }
+            buffer.extend(read_buffer.iter());
+        }
 
-            // How many frames are available on the input stream?
-            let available_frames = match wait_for_stream(|| stream.get_stream_read_available()) {
-                Ok(frames) => frames,
-                Err(err) => {
-                    println!("An error occurred while requesting the number of available \
-                             frames for reading from the input stream: {}. BlockingStream will \
-                             now exit the event loop.", StdError::description(&err));
-                    return None;
-                },
-            };
-
-            // If there are frames available and we have room in the buffer, take them.
-            if available_frames > 0 && buffer.capacity() >= buffer.len() + available_frames as usize {
-                match stream.read(available_frames) {
-                    Ok(input_samples) => buffer.extend(input_samples.into_iter()),
-                    Err(err) => {
-                        println!("An error occurred while reading from the input stream: {}. \
-                                 BlockingStream will now exit the event loop.",
-                                 StdError::description(&err));
-                        return None;
-                    },
-                }
-            }
+        Ok(())
+    }
+
+}
+
+#[cfg(feature="futures")]
+impl<I> BufferStream<I> {
+
+    /// Hand a buffer back to be reused by the audio callback.
+    ///
+    /// The callback never allocates, so any buffer that is not handed back is lost to the pool.
+    pub fn recycle(&mut self, buffer: Vec<I>) {
+        let _ = self.recycled.try_send(buffer);
+    }
+
+    /// The total number of buffers dropped as the stream was not polled often enough.
+    pub fn overflows(&self) -> usize {
+        self.events.overflows()
+    }
 
+}
+
+#[cfg(feature="futures")]
+impl<I> Stream for BufferStream<I> {
+    type Item = AsyncEvent<I>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<AsyncEvent<I>>> {
+        // Register before checking the queue so that a buffer pushed in between still wakes us.
+        self.wakeup.register(cx.waker());
+        if let Some(event) = self.events.pop() {
+            return Poll::Ready(Some(event));
         }
+        if self.wakeup.is_closed() {
+            // The callback may have pushed a final buffer before it was dropped.
+            return Poll::Ready(self.events.pop());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature="futures"))]
+mod tests {
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use {Offline, SoundStream, StreamParams};
 
+    struct NoWake;
+
+    impl Wake for NoWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn buffer_stream_ends_once_closed() {
+        let offline = Offline::new().channels(1, 0);
+        let (mut stream, buffers) = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(2)
+            .input(StreamParams::<f32>::new())
+            .run_stream(4)
+            .unwrap();
+        let waker = Waker::from(Arc::new(NoWake));
+        let mut cx = Context::from_waker(&waker);
+        let mut buffers = buffers;
+
+        offline.push_input(&[1.0f32, 2.0, 3.0, 4.0]);
+        offline.process(4);
+        stream.close().unwrap();
+
+        let mut samples = Vec::new();
+        loop {
+            match Pin::new(&mut buffers).poll_next(&mut cx) {
+                Poll::Ready(Some(event)) => samples.extend(event.0),
+                Poll::Ready(None) => break,
+                Poll::Pending => panic!("the stream should end once closed"),
+            }
+        }
+        // Buffers read before the stream was closed are still yielded.
+        assert_eq!(samples, vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn unrecycled_buffers_are_dropped() {
+        let offline = Offline::new().channels(1, 0);
+        let (_stream, buffers) = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(2)
+            .input(StreamParams::<f32>::new())
+            .run_stream(2)
+            .unwrap();
+        let waker = Waker::from(Arc::new(NoWake));
+        let mut cx = Context::from_waker(&waker);
+        let mut buffers = buffers;
+
+        // Both pooled buffers are taken, after which input is dropped rather than allocating.
+        offline.process(2 * 2);
+        for _ in 0..2 {
+            match Pin::new(&mut buffers).poll_next(&mut cx) {
+                Poll::Ready(Some(_)) => (),
+                _ => panic!("expected a buffer"),
+            }
+        }
+        offline.process(2);
+        assert!(Pin::new(&mut buffers).poll_next(&mut cx).is_pending());
+        assert_eq!(buffers.overflows(), 1);
     }
 
 }
@@ -1,11 +1,23 @@
 
-use error::Error;
+use backend::{Backend, PortAudio};
+use device::{DeviceInfo, DeviceName};
+use diagnostics::{Diagnostic, DiagnosticSink, Diagnostics};
+use error::{Error, Xrun};
+use format::{DeviceFormat, StreamFormat};
 use portaudio::pa;
 use portaudio::pa::Sample as PaSample;
-use sample::Format as SampleFormat;
 use sample::Sample;
-use settings::Frames;
+use settings::{Frames, Settings};
 use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature="futures")]
+use atomic_waker::AtomicWaker;
+#[cfg(feature="futures")]
+use std::sync::atomic::AtomicBool;
+#[cfg(feature="futures")]
+use std::task::Waker;
 
 pub mod duplex;
 pub mod input;
@@ -16,17 +28,62 @@ pub const MINIMUM_BUFFER_RESERVATION: usize = 2048;
 
 /// A builder context for a SoundStream.
 #[derive(Clone, PartialEq)]
-pub struct SoundStream {
+pub struct SoundStream<B=PortAudio> {
     maybe_buffer_frequency: Option<BufferFrequency>,
-    maybe_sample_hz: Option<f64>,
+    sample_hz_preferences: Vec<f64>,
     maybe_flags: Option<StreamFlags>,
+    strict_channels: bool,
+    diagnostics: Diagnostics,
+    backend: B,
 }
 
 /// Bit flags to be passed to the stream.
 pub type StreamFlags = pa::StreamFlags;
 
-/// Bit flags fed to the callback to indicate non-blocking stream behaviour.
-pub type CallbackFlags = pa::StreamCallbackFlags;
+/// Flags fed to the callback to indicate any under or overflow that has occurred since the
+/// previous buffer.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallbackFlags {
+    /// The input buffer contains silence in place of samples that were not yet available.
+    pub input_underflow: bool,
+    /// Input samples were discarded as the callback did not consume them in time.
+    pub input_overflow: bool,
+    /// Silence was played in place of samples that the callback did not produce in time.
+    pub output_underflow: bool,
+    /// Some of the output buffer will be discarded as the device is not ready for it.
+    pub output_overflow: bool,
+    /// The output buffer is used to prime the stream before it starts and its input is silence.
+    pub priming_output: bool,
+}
+
+/// The number of each kind of under or overflow that has occurred on a stream.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct XrunStats {
+    /// The number of input buffers that were padded with silence.
+    pub input_underflows: usize,
+    /// The number of times input samples were discarded before the callback could read them.
+    pub input_overflows: usize,
+    /// The number of output buffers that were played with silence in place of missing samples.
+    pub output_underflows: usize,
+    /// The number of times output samples were discarded as the device was not ready for them.
+    pub output_overflows: usize,
+    /// The number of buffers used to prime the stream's output.
+    pub priming_outputs: usize,
+}
+
+/// Accumulates the `XrunStats` of a stream. Clones share the same counts so that they may be
+/// updated on the audio thread and read from the stream's handle.
+#[derive(Clone)]
+pub struct XrunCounter(Arc<[AtomicUsize; XRUN_KINDS]>);
+
+/// The index of each count within an `XrunCounter`.
+const INPUT_UNDERFLOWS: usize = 0;
+const INPUT_OVERFLOWS: usize = 1;
+const OUTPUT_UNDERFLOWS: usize = 2;
+const OUTPUT_OVERFLOWS: usize = 3;
+const PRIMING_OUTPUTS: usize = 4;
+/// The number of counts held by an `XrunCounter`.
+const XRUN_KINDS: usize = 5;
 
 /// Represents the update frequency.
 #[derive(Copy, Clone, PartialEq)]
@@ -38,6 +95,32 @@ enum BufferFrequency {
 /// Difference in time between Update events.
 pub type DeltaTimeSeconds = f64;
 
+/// Timing information for a buffer passed to a non-blocking stream's callback.
+///
+/// All times are in seconds and are measured by the backend's clock, so they may be compared
+/// with other timestamps (i.e. MIDI events) taken from the same clock.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StreamTime {
+    /// The time at which the callback was called.
+    pub current: f64,
+    /// The time at which the first sample of the input buffer was captured by the ADC.
+    pub input_adc: f64,
+    /// The time at which the first sample of the output buffer will be played by the DAC.
+    pub output_dac: f64,
+    /// The index of the buffer's first frame, counting every frame since the stream started.
+    pub frame: u64,
+    /// The time elapsed since the previous buffer.
+    pub dt: DeltaTimeSeconds,
+}
+
+/// Produces the `StreamTime` for each successive buffer within a non-blocking stream's callback.
+pub struct Timer {
+    maybe_last_time: Option<f64>,
+    frame: u64,
+    /// The running frame count, shared with the stream's handle.
+    frames_processed: Arc<AtomicU64>,
+}
+
 /// To be returned by the callback that is run by the non-blocking streams.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CallbackResult {
@@ -49,6 +132,209 @@ pub enum CallbackResult {
     Abort,
 }
 
+impl Timer {
+
+    /// Construct a Timer for a stream that has not yet processed any frames.
+    pub fn new() -> Timer {
+        Timer {
+            maybe_last_time: None,
+            frame: 0,
+            frames_processed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// A handle to the running frame count for reading from outside the callback.
+    pub fn frames_processed(&self) -> FramesProcessed {
+        FramesProcessed(self.frames_processed.clone())
+    }
+
+    /// The `StreamTime` for a buffer of the given number of frames, advancing the frame count.
+    pub fn next(&mut self, time_info: &pa::StreamCallbackTimeInfo, frames: u32) -> StreamTime {
+        let current = time_info.current_time;
+        let time = StreamTime {
+            current,
+            input_adc: time_info.input_buffer_adc_time,
+            output_dac: time_info.output_buffer_dac_time,
+            frame: self.frame,
+            dt: current - self.maybe_last_time.unwrap_or(current),
+        };
+        self.maybe_last_time = Some(current);
+        self.frame += frames as u64;
+        self.frames_processed.store(self.frame, Ordering::Relaxed);
+        time
+    }
+
+}
+
+/// A handle to the number of frames processed by a non-blocking stream's callback.
+pub struct FramesProcessed(Arc<AtomicU64>);
+
+impl FramesProcessed {
+
+    /// The number of frames processed so far.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+}
+
+/// The sample rate of a non-blocking stream, shared with its callback.
+///
+/// The rate at which the device actually runs is only known once the stream has been opened, so
+/// the callback reads it from here rather than capturing the requested rate.
+#[derive(Clone)]
+struct SharedSampleHz(Arc<AtomicU64>);
+
+impl SharedSampleHz {
+
+    /// A handle holding the requested sample rate until the actual one is known.
+    fn new(sample_hz: f64) -> SharedSampleHz {
+        SharedSampleHz(Arc::new(AtomicU64::new(sample_hz.to_bits())))
+    }
+
+    /// The current sample rate.
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Replace the sample rate, e.g. with the one reported by the opened stream.
+    fn set(&self, sample_hz: f64) {
+        self.0.store(sample_hz.to_bits(), Ordering::Relaxed);
+    }
+
+}
+
+/// Wakes the task polling an async adapter whenever the audio callback makes progress.
+///
+/// One `Notifier` is moved into the callback and another is held by the stream. The adapter is
+/// marked as closed as soon as either is dropped, which happens when the stream is closed.
+#[cfg(feature="futures")]
+pub struct Notifier(Arc<WakeupState>);
+
+/// The adapter's half of a `Notifier`.
+#[cfg(feature="futures")]
+pub struct Wakeup(Arc<WakeupState>);
+
+#[cfg(feature="futures")]
+struct WakeupState {
+    waker: AtomicWaker,
+    is_closed: AtomicBool,
+}
+
+/// Construct a connected `Notifier` and `Wakeup`.
+#[cfg(feature="futures")]
+pub fn wakeup() -> (Notifier, Wakeup) {
+    let state = Arc::new(WakeupState {
+        waker: AtomicWaker::new(),
+        is_closed: AtomicBool::new(false),
+    });
+    (Notifier(state.clone()), Wakeup(state))
+}
+
+#[cfg(feature="futures")]
+impl Notifier {
+
+    /// Wake the most recently registered task, if any.
+    pub fn notify(&self) {
+        self.0.waker.wake();
+    }
+
+}
+
+#[cfg(feature="futures")]
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        self.0.is_closed.store(true, Ordering::Release);
+        self.0.waker.wake();
+    }
+}
+
+#[cfg(feature="futures")]
+impl Wakeup {
+
+    /// Register the polling task's waker to be woken by the `Notifier`.
+    ///
+    /// Must be called before checking for progress so that none is missed.
+    pub fn register(&self, waker: &Waker) {
+        self.0.waker.register(waker);
+    }
+
+    /// Another `Notifier` for the same adapter.
+    pub fn notifier(&self) -> Notifier {
+        Notifier(self.0.clone())
+    }
+
+    /// Whether or not a `Notifier` has been dropped, i.e. the stream has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed.load(Ordering::Acquire)
+    }
+
+}
+
+impl CallbackFlags {
+
+    /// No flags set.
+    pub fn empty() -> CallbackFlags {
+        CallbackFlags::default()
+    }
+
+    /// Whether or not any under or overflow occurred.
+    pub fn is_xrun(&self) -> bool {
+        self.input_underflow || self.input_overflow || self.output_underflow || self.output_overflow
+    }
+
+}
+
+impl From<pa::StreamCallbackFlags> for CallbackFlags {
+    fn from(flags: pa::StreamCallbackFlags) -> CallbackFlags {
+        // The values of PortAudio's `paInputUnderflow`, `paInputOverflow`, etc.
+        let bits = flags.bits();
+        CallbackFlags {
+            input_underflow: bits & 0x1 != 0,
+            input_overflow: bits & 0x2 != 0,
+            output_underflow: bits & 0x4 != 0,
+            output_overflow: bits & 0x8 != 0,
+            priming_output: bits & 0x10 != 0,
+        }
+    }
+}
+
+impl XrunCounter {
+
+    /// Construct a counter with no xruns recorded.
+    pub fn new() -> XrunCounter {
+        XrunCounter(Arc::new([AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0),
+                              AtomicUsize::new(0), AtomicUsize::new(0)]))
+    }
+
+    /// Count each flag that is set.
+    pub fn record(&self, flags: CallbackFlags) {
+        let set = [(INPUT_UNDERFLOWS, flags.input_underflow),
+                   (INPUT_OVERFLOWS, flags.input_overflow),
+                   (OUTPUT_UNDERFLOWS, flags.output_underflow),
+                   (OUTPUT_OVERFLOWS, flags.output_overflow),
+                   (PRIMING_OUTPUTS, flags.priming_output)];
+        for &(idx, is_set) in set.iter() {
+            if is_set {
+                self.0[idx].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The counts recorded so far.
+    pub fn stats(&self) -> XrunStats {
+        let count = |idx: usize| self.0[idx].load(Ordering::Relaxed);
+        XrunStats {
+            input_underflows: count(INPUT_UNDERFLOWS),
+            input_overflows: count(INPUT_OVERFLOWS),
+            output_underflows: count(OUTPUT_UNDERFLOWS),
+            output_overflows: count(OUTPUT_OVERFLOWS),
+            priming_outputs: count(PRIMING_OUTPUTS),
+        }
+    }
+
+}
+
 /// The params unwrapped by the input and output stream builders.
 pub type PaParams = (StreamFlags, pa::StreamParameters, f64, u32);
 
@@ -58,10 +344,19 @@ pub type Idx = pa::DeviceIndex;
 /// A suggested amount of latency.
 pub type Latency = pa::Time;
 
+/// The direction of audio within a stream.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
 /// A type for building stream parameters.
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct StreamParams<S> {
     pub idx: Option<Idx>,
+    pub device_name: Option<DeviceName>,
+    pub host_api: Option<String>,
     pub channel_count: Option<i32>,
     pub suggested_latency: Option<Latency>,
     pub phantom_sample: PhantomData<S>,
@@ -69,45 +364,99 @@ pub struct StreamParams<S> {
 
 impl SoundStream {
 
-    /// Constructs the builder for a new SoundStream.
+    /// Constructs the builder for a new SoundStream using the default PortAudio backend.
     #[inline]
     pub fn new() -> SoundStream {
         SoundStream {
             maybe_buffer_frequency: None,
-            maybe_sample_hz: None,
+            sample_hz_preferences: Vec::new(),
             maybe_flags: None,
+            strict_channels: false,
+            diagnostics: Diagnostics::default(),
+            backend: PortAudio,
+        }
+    }
+
+}
+
+impl Default for SoundStream {
+    fn default() -> SoundStream {
+        SoundStream::new()
+    }
+}
+
+impl<B> SoundStream<B> where B: Backend {
+
+    /// The backend used to open the stream.
+    #[inline]
+    pub fn backend<C>(self, backend: C) -> SoundStream<C> where C: Backend {
+        let SoundStream {
+            maybe_buffer_frequency, sample_hz_preferences, maybe_flags, strict_channels,
+            diagnostics, ..
+        } = self;
+        SoundStream {
+            maybe_buffer_frequency,
+            sample_hz_preferences,
+            maybe_flags,
+            strict_channels,
+            diagnostics,
+            backend,
         }
     }
 
+    /// The sink to which the stream's diagnostics are sent, i.e. the errors that end a blocking
+    /// stream's event loop. By default they are written to stderr, or forwarded to the `log`
+    /// crate when the "log" feature is enabled.
+    #[inline]
+    pub fn diagnostics<S>(self, sink: S) -> SoundStream<B> where S: DiagnosticSink + 'static {
+        SoundStream { diagnostics: Diagnostics::new(sink), ..self }
+    }
+
     /// Desired stream sample rate (samples per second). For a duplex stream, it is the sample rate
     /// for both the input and output streams.
     #[inline]
-    pub fn sample_hz(self, sample_hz: f64) -> SoundStream {
-        SoundStream { maybe_sample_hz: Some(sample_hz), ..self }
+    pub fn sample_hz(self, sample_hz: f64) -> SoundStream<B> {
+        SoundStream { sample_hz_preferences: vec![sample_hz], ..self }
+    }
+
+    /// Desired stream sample rates in order of preference. The first rate supported by the
+    /// device(s) is used and may be retrieved from the running stream's `Settings`.
+    #[inline]
+    pub fn sample_hz_preferences(self, preferences: &[f64]) -> SoundStream<B> {
+        SoundStream { sample_hz_preferences: preferences.to_vec(), ..self }
     }
 
     /// Flags indicating stream behaviour.
     #[inline]
-    pub fn flags(self, flags: StreamFlags) -> SoundStream {
+    pub fn flags(self, flags: StreamFlags) -> SoundStream<B> {
         SoundStream { maybe_flags: Some(flags), ..self }
     }
 
+    /// Return an error when a device cannot provide the requested number of channels.
+    ///
+    /// By default the requested number of channels is clamped to the device's maximum. The
+    /// negotiated parameters may be retrieved from the running stream either way.
+    #[inline]
+    pub fn strict_channels(self) -> SoundStream<B> {
+        SoundStream { strict_channels: true, ..self }
+    }
+
     /// Used to calculate the number of frames per buffer.
     #[inline]
-    pub fn buffer_hz(self, hz: f32) -> SoundStream {
+    pub fn buffer_hz(self, hz: f32) -> SoundStream<B> {
         assert!(hz > 0.0, "`update_hz` must be greater than 0.0, but you gave {:?}", hz);
         SoundStream { maybe_buffer_frequency: Some(BufferFrequency::Hz(hz)), ..self }
     }
 
     /// The number of frames per buffer of audio.
     #[inline]
-    pub fn frames_per_buffer(self, frames: Frames) -> SoundStream {
+    pub fn frames_per_buffer(self, frames: Frames) -> SoundStream<B> {
         SoundStream { maybe_buffer_frequency: Some(BufferFrequency::Frames(frames)), ..self }
     }
 
     /// Custom input device.
     #[inline]
-    pub fn input<I>(self, params: StreamParams<I>) -> input::Builder<I>
+    pub fn input<I>(self, params: StreamParams<I>) -> input::Builder<I, B>
         where
             I: Sample + PaSample
     {
@@ -116,7 +465,7 @@ impl SoundStream {
 
     /// Custom output device.
     #[inline]
-    pub fn output<O>(self, params: StreamParams<O>) -> output::Builder<O>
+    pub fn output<O>(self, params: StreamParams<O>) -> output::Builder<O, B>
         where
             O: Sample + PaSample
     {
@@ -127,26 +476,34 @@ impl SoundStream {
     #[inline]
     pub fn duplex<I, O>(self,
                         input_params: StreamParams<I>,
-                        output_params: StreamParams<O>) -> duplex::Builder<I, O>
+                        output_params: StreamParams<O>) -> duplex::Builder<I, O, B>
         where
             I: Sample + PaSample,
             O: Sample + PaSample,
     {
         duplex::Builder {
             stream_params: self,
-            input_params: input_params,
-            output_params: output_params,
+            input_params,
+            output_params,
         }
     }
 
 }
 
+impl<S> Default for StreamParams<S> {
+    fn default() -> StreamParams<S> {
+        StreamParams::new()
+    }
+}
+
 impl<S> StreamParams<S> {
 
     /// Construct a default StreamParams.
     pub fn new() -> StreamParams<S> {
         StreamParams {
             idx: None,
+            device_name: None,
+            host_api: None,
             channel_count: None,
             suggested_latency: None,
             phantom_sample: PhantomData,
@@ -159,6 +516,38 @@ impl<S> StreamParams<S> {
         StreamParams { idx: Some(idx), ..self }
     }
 
+    /// Specify the device to be used for the Stream.
+    #[inline]
+    pub fn device(self, info: &DeviceInfo) -> StreamParams<S> {
+        StreamParams { idx: Some(info.idx), ..self }
+    }
+
+    /// Specify the device to be used for the Stream by a case-insensitive substring of its name.
+    ///
+    /// The name is resolved when the stream is run. It is an error if no device or more than one
+    /// device matches.
+    #[inline]
+    pub fn device_name(self, name: &str) -> StreamParams<S> {
+        StreamParams { device_name: Some(DeviceName::Contains(name.to_string())), ..self }
+    }
+
+    /// Specify the device to be used for the Stream by a regular expression matching its name.
+    ///
+    /// The pattern is resolved when the stream is run. It is an error if no device or more than
+    /// one device matches.
+    #[cfg(feature="regex")]
+    #[inline]
+    pub fn device_regex(self, pattern: &str) -> StreamParams<S> {
+        StreamParams { device_name: Some(DeviceName::Regex(pattern.to_string())), ..self }
+    }
+
+    /// Only consider devices provided by the host API whose name contains the given string,
+    /// ignoring case (i.e. "ALSA", "JACK", "Core Audio").
+    #[inline]
+    pub fn host_api(self, host_api: &str) -> StreamParams<S> {
+        StreamParams { host_api: Some(host_api.to_string()), ..self }
+    }
+
     /// Request a number of channels for the Stream.
     #[inline]
     pub fn channels(self, channels: i32) -> StreamParams<S> {
@@ -179,20 +568,399 @@ impl<S> StreamParams<S> {
 
 }
 
-/// Wait for the given stream to become ready for reading/writing.
-fn wait_for_stream<F>(f: F) -> Result<u32, Error>
+impl<B> SoundStream<B> {
+
+    /// Check that the requested values are valid for any device.
+    fn validate(&self) -> Result<(), Error> {
+        for &sample_hz in self.sample_hz_preferences.iter() {
+            if sample_hz.is_nan() || sample_hz <= 0.0 {
+                return Err(Error::InvalidSettings(format!(
+                    "the sample rate must be greater than 0.0, but {} was requested", sample_hz)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Determine the sample rate for a stream with the given parameters.
+    ///
+    /// If no rate was requested, the default rate of the input device (or the output device if
+    /// there is no input) is used. If several rates were requested, the first that the backend
+    /// supports is chosen. If none are supported, the error for the most preferred is returned.
+    fn negotiate_sample_hz(&self,
+                           input: Option<&pa::StreamParameters>,
+                           output: Option<&pa::StreamParameters>) -> Result<f64, Error>
+        where B: Backend,
+    {
+        let backend = &self.backend;
+        match self.sample_hz_preferences.len() {
+            0 => {
+                let device = input.or(output).map(|params| params.device).unwrap_or(0);
+                Ok(backend.device_info(device)?.default_sample_hz)
+            },
+            1 => Ok(self.sample_hz_preferences[0]),
+            _ => {
+                let mut maybe_first_err = None;
+                for &sample_hz in self.sample_hz_preferences.iter() {
+                    match check_format(backend, input, output, sample_hz) {
+                        Ok(_) => return Ok(sample_hz),
+                        Err(err @ Error::UnsupportedFormat(_)) =>
+                            if maybe_first_err.is_none() { maybe_first_err = Some(err) },
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(maybe_first_err.unwrap())
+            },
+        }
+    }
+
+}
+
+impl<S> StreamParams<S> {
+
+    /// Check that the requested values are valid for any device.
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(channels) = self.channel_count {
+            if channels <= 0 {
+                return Err(Error::InvalidSettings(format!(
+                    "at least 1 channel is required, but {} were requested", channels)));
+            }
+        }
+        if let Some(latency) = self.suggested_latency {
+            if latency < 0.0 {
+                return Err(Error::InvalidSettings(format!(
+                    "the suggested latency must not be negative, but {} was requested", latency)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The parameters that were negotiated with the device for a running stream.
+    fn negotiated(params: &pa::StreamParameters) -> StreamParams<S> {
+        StreamParams {
+            idx: Some(params.device),
+            device_name: None,
+            host_api: None,
+            channel_count: Some(params.channel_count),
+            suggested_latency: Some(params.suggested_latency),
+            phantom_sample: PhantomData,
+        }
+    }
+
+    /// Determine the number of channels to request from the given device.
+    ///
+    /// Channel counts exceeding the device's maximum are clamped unless `strict` is true, in which
+    /// case an `UnsupportedChannels` error is returned. If no count was requested, stereo is used
+    /// where possible.
+    fn negotiate_channels(&self, info: &DeviceInfo, direction: Direction, strict: bool)
+        -> Result<i32, Error>
+    {
+        let max = match direction {
+            Direction::Input => info.max_input_channels,
+            Direction::Output => info.max_output_channels,
+        };
+        match self.channel_count {
+            Some(n) if n > max && strict => Err(Error::UnsupportedChannels {
+                device: info.name.clone(),
+                requested: n,
+                max,
+            }),
+            Some(n) => Ok(::std::cmp::min(n, max)),
+            None => Ok(::std::cmp::min(2, max)),
+        }
+    }
+
+    /// Determine the index of the device to use for the given direction.
+    ///
+    /// An index given via `device_idx` takes precedence, followed by the `device_name` and
+    /// `host_api` filters, before falling back to the backend's default device.
+    fn resolve_device<B>(&self, backend: &B, direction: Direction) -> Result<Idx, Error>
+        where B: Backend,
+    {
+        if let Some(idx) = self.idx {
+            return Ok(idx);
+        }
+
+        if self.device_name.is_none() && self.host_api.is_none() {
+            return match direction {
+                Direction::Input => backend.default_input_device(),
+                Direction::Output => backend.default_output_device(),
+            };
+        }
+
+        // Compile the name pattern once rather than for every device.
+        let maybe_matcher = match self.device_name {
+            Some(ref device_name) => Some(device_name.matcher()?),
+            None => None,
+        };
+
+        let mut matches = Vec::new();
+        for device in backend.devices()? {
+            let supports_direction = match direction {
+                Direction::Input => device.is_input(),
+                Direction::Output => device.is_output(),
+            };
+            if !supports_direction {
+                continue;
+            }
+            if let Some(ref host_api) = self.host_api {
+                if !device.host_api.to_lowercase().contains(&host_api.to_lowercase()) {
+                    continue;
+                }
+            }
+            if let Some(ref matcher) = maybe_matcher {
+                if !matcher.matches(&device.name) {
+                    continue;
+                }
+            }
+            matches.push(device);
+        }
+
+        // A device named exactly as requested wins over those whose names merely contain it.
+        if let Some(ref matcher) = maybe_matcher {
+            if matches.iter().any(|device| matcher.is_exact(&device.name)) {
+                matches.retain(|device| matcher.is_exact(&device.name));
+            }
+        }
+
+        match matches.len() {
+            1 => Ok(matches[0].idx),
+            0 => Err(Error::DeviceNotFound(self.describe_device(direction))),
+            _ => Err(Error::AmbiguousDevice(self.describe_device(direction),
+                                            matches.into_iter().map(|d| d.name).collect())),
+        }
+    }
+
+    /// A description of the requested device for use within error messages.
+    fn describe_device(&self, direction: Direction) -> String {
+        let mut description = match direction {
+            Direction::Input => "input device".to_string(),
+            Direction::Output => "output device".to_string(),
+        };
+        if let Some(ref device_name) = self.device_name {
+            description.push_str(&format!(" with {}", device_name));
+        }
+        if let Some(ref host_api) = self.host_api {
+            description.push_str(&format!(" on host API {:?}", host_api));
+        }
+        description
+    }
+
+}
+
+/// Describe the format requested by the given stream parameters.
+fn stream_format<B>(backend: &B,
+                    input: Option<&pa::StreamParameters>,
+                    output: Option<&pa::StreamParameters>,
+                    sample_hz: f64) -> StreamFormat
+    where B: Backend,
+{
+    let device_format = |params: &pa::StreamParameters| DeviceFormat {
+        idx: params.device,
+        device: backend.device_info(params.device)
+            .map(|info| info.name)
+            .unwrap_or_else(|_| format!("device {}", params.device)),
+        channels: params.channel_count,
+        sample_format: params.sample_format,
+    };
+    StreamFormat {
+        sample_hz,
+        input: input.map(&device_format),
+        output: output.map(&device_format),
+    }
+}
+
+/// Replace the backend's errors about an unsupported format with an `UnsupportedFormat` error
+/// describing the requested format.
+fn format_error<B>(err: Error,
+                   backend: &B,
+                   input: Option<&pa::StreamParameters>,
+                   output: Option<&pa::StreamParameters>,
+                   sample_hz: f64) -> Error
+    where B: Backend,
+{
+    match err {
+        Error::PortAudio(pa::Error::InvalidChannelCount) |
+        Error::PortAudio(pa::Error::InvalidSampleRate) |
+        Error::PortAudio(pa::Error::SampleFormatNotSupported) =>
+            Error::UnsupportedFormat(stream_format(backend, input, output, sample_hz)),
+        err => err,
+    }
+}
+
+/// Ask the backend whether the given stream parameters are supported.
+///
+/// Returns a description of the format if so, otherwise an `UnsupportedFormat` error describing
+/// the requested format, or the backend's own error if the reason is unrelated to the format.
+fn check_format<B>(backend: &B,
+                   input: Option<&pa::StreamParameters>,
+                   output: Option<&pa::StreamParameters>,
+                   sample_hz: f64) -> Result<StreamFormat, Error>
+    where B: Backend,
+{
+    match backend.is_format_supported(input, output, sample_hz) {
+        Ok(()) => Ok(stream_format(backend, input, output, sample_hz)),
+        Err(err) => Err(format_error(err, backend, input, output, sample_hz)),
+    }
+}
+
+/// The number of samples to allocate up front for each of a stream's buffers, falling back to
+/// `MINIMUM_BUFFER_RESERVATION` should the number of frames per buffer be variable.
+#[cfg(feature="futures")]
+fn buffer_len(settings: Settings) -> usize {
+    match settings.frames {
+        0 => MINIMUM_BUFFER_RESERVATION,
+        frames => frames as usize * settings.channels as usize,
+    }
+}
+
+/// The most frames that a planar callback is given at once, so that its scratch buffers may be
+/// allocated up front. With a variable number of frames per buffer, larger buffers are split.
+fn planar_frames(settings: Settings) -> usize {
+    match settings.frames {
+        0 => MINIMUM_BUFFER_RESERVATION,
+        frames => frames as usize,
+    }
+}
+
+/// Call `f` with the range, `Settings` and `StreamTime` of each consecutive chunk of at most
+/// `max_frames` frames within a buffer, as though each chunk were a buffer of its own.
+///
+/// Stops at the first chunk for which `f` returns anything other than `CallbackResult::Continue`.
+fn for_each_chunk<F>(settings: Settings, time: StreamTime, max_frames: usize, mut f: F)
+    -> CallbackResult
+    where F: FnMut(Range<usize>, Settings, StreamTime) -> CallbackResult,
+{
+    let frames = settings.frames as usize;
+    let mut start = 0;
+    loop {
+        let end = ::std::cmp::min(start + max_frames, frames);
+        let offset = start as f64 / settings.exact_sample_hz;
+        let chunk_settings = Settings { frames: (end - start) as u32, ..settings };
+        let chunk_time = StreamTime {
+            current: time.current + offset,
+            input_adc: time.input_adc + offset,
+            output_dac: time.output_dac + offset,
+            frame: time.frame + start as u64,
+            dt: if start == 0 { time.dt } else { max_frames as f64 / settings.exact_sample_hz },
+        };
+        match f(start..end, chunk_settings, chunk_time) {
+            CallbackResult::Continue if end < frames => start = end,
+            result => return result,
+        }
+    }
+}
+
+/// Wait for the given stream to become ready for reading/writing, counting and reporting any
+/// xruns that occur while waiting.
+fn wait_for_stream<F>(f: F, xruns: &XrunCounter, diagnostics: &Diagnostics) -> Result<u32, Error>
     where
-        F: Fn() -> Result<pa::StreamAvailable, pa::Error>,
+        F: Fn() -> Result<pa::StreamAvailable, Error>,
 {
     loop {
         match f() {
             Ok(available) => match available {
                 pa::StreamAvailable::Frames(frames) => return Ok(frames as u32),
-                pa::StreamAvailable::InputOverflowed => println!("Input stream has overflowed"),
-                pa::StreamAvailable::OutputUnderflowed => println!("Output stream has underflowed"),
+                pa::StreamAvailable::InputOverflowed => {
+                    let flags = CallbackFlags { input_overflow: true, ..CallbackFlags::empty() };
+                    xruns.record(flags);
+                    diagnostics.emit(Diagnostic::Xrun(Xrun::InputOverflow));
+                },
+                pa::StreamAvailable::OutputUnderflowed => {
+                    let flags = CallbackFlags { output_underflow: true, ..CallbackFlags::empty() };
+                    xruns.record(flags);
+                    diagnostics.emit(Diagnostic::Xrun(Xrun::OutputUnderflow));
+                },
             },
-            Err(err) => return Err(Error::PortAudio(err)),
+            Err(err) => return Err(err),
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Idx, SoundStream, StreamParams};
+    use error::Error;
+    use Offline;
+
+    fn offline() -> Offline {
+        Offline::new()
+            .add_device("Offline Output Monitor", 0, 2)
+            .add_device("USB Microphone", 1, 0)
+    }
+
+    fn resolve(params: StreamParams<f32>, direction: Direction) -> Result<Idx, Error> {
+        params.resolve_device(&offline(), direction)
+    }
+
+    #[test]
+    fn defaults_are_used_without_filters() {
+        assert_eq!(resolve(StreamParams::new(), Direction::Input).unwrap(), 0);
+        assert_eq!(resolve(StreamParams::new(), Direction::Output).unwrap(), 1);
+        assert_eq!(resolve(StreamParams::new().device_idx(2), Direction::Output).unwrap(), 2);
+    }
+
+    #[test]
+    fn substrings_match_devices_of_the_given_direction() {
+        let params = StreamParams::new().device_name("MONITOR");
+        assert_eq!(resolve(params, Direction::Output).unwrap(), 2);
+        let params = StreamParams::new().device_name("usb");
+        assert_eq!(resolve(params, Direction::Input).unwrap(), 3);
+        match resolve(StreamParams::new().device_name("usb"), Direction::Output) {
+            Err(Error::DeviceNotFound(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match resolve(StreamParams::new().host_api("jack"), Direction::Output) {
+            Err(Error::DeviceNotFound(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exact_names_win_over_substrings() {
+        let params = StreamParams::new().device_name("offline output");
+        assert_eq!(resolve(params, Direction::Output).unwrap(), 1);
+        match resolve(StreamParams::new().device_name("output"), Direction::Output) {
+            Err(Error::AmbiguousDevice(_, names)) =>
+                assert_eq!(names, vec!["Offline Output", "Offline Output Monitor"]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_first_supported_sample_hz_preference_is_used() {
+        let stream = SoundStream::new()
+            .backend(Offline::new().sample_hz(44_100.0))
+            .sample_hz_preferences(&[48_000.0, 44_100.0, 22_050.0])
+            .output(StreamParams::<f32>::new())
+            .run()
+            .unwrap();
+        assert_eq!(stream.settings().exact_sample_hz, 44_100.0);
+    }
+
+    #[test]
+    fn unsupported_sample_hz_preferences_are_an_error() {
+        let result = SoundStream::new()
+            .backend(Offline::new().sample_hz(44_100.0))
+            .sample_hz_preferences(&[48_000.0, 96_000.0])
+            .output(StreamParams::<f32>::new())
+            .run();
+        match result {
+            Err(Error::UnsupportedFormat(_)) => (),
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(_) => panic!("no preference is supported"),
+        }
+    }
+
+    #[cfg(feature="regex")]
+    #[test]
+    fn regexes_match_device_names() {
+        let params = StreamParams::new().device_regex("^Offline Output$");
+        assert_eq!(resolve(params, Direction::Output).unwrap(), 1);
+        match resolve(StreamParams::new().device_regex("("), Direction::Output) {
+            Err(Error::InvalidDevicePattern(..)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+}
@@ -1,108 +1,190 @@
 
+use backend::{Backend, Host, PortAudio, RawStream};
+use buffer::BufferMut;
+use diagnostics::{Diagnostic, Diagnostics, StreamOperation};
 use error::Error;
+use format::StreamFormat;
+use planar::PlanarMut;
+#[cfg(feature="futures")]
+use futures_sink::Sink;
 use portaudio::pa;
 use portaudio::pa::Sample as PaSample;
 use sample::{Sample, Wave};
-use settings::{Channels, Settings, Frames, SampleHz};
+use settings::{Channels, Settings, Frames};
+use spsc::{self, Consumer, Drain, Producer, Sender};
+#[cfg(feature="futures")]
+use spsc::Receiver;
 use std::collections::VecDeque;
-use std::marker::PhantomData;
+#[cfg(feature="futures")]
+use std::pin::Pin;
+#[cfg(feature="futures")]
+use std::task::{Context, Poll};
+use utils::{drain_front, interleave};
 
 use super::{
     BufferFrequency,
     CallbackFlags,
     CallbackResult,
-    DeltaTimeSeconds,
+    Direction,
+    FramesProcessed,
     MINIMUM_BUFFER_RESERVATION,
     PaParams,
+    SharedSampleHz,
     SoundStream,
     StreamFlags,
     StreamParams,
+    StreamTime,
+    Timer,
+    XrunCounter,
+    XrunStats,
+    check_format,
+    for_each_chunk,
+    format_error,
+    planar_frames,
     wait_for_stream,
 };
+#[cfg(feature="futures")]
+use super::{Notifier, Wakeup, wakeup};
 
 
 /// A builder context for an Output sound stream.
-pub struct Builder<O> {
-    pub stream_params: SoundStream,
+pub struct Builder<O, B=PortAudio> {
+    pub stream_params: SoundStream<B>,
     pub output_params: StreamParams<O>,
 }
 
-/// An iterator of blocking output stream events.
-pub struct BlockingStream<'a, O=Wave> where O: Sample + PaSample {
+/// A blocking output stream, yielding each buffer to be written to via `next_buffer`.
+pub struct BlockingStream<O=Wave, B=PortAudio> where O: Sample + PaSample, B: Backend {
     /// Buffer the samples from the output until its length is equal to the buffer_length.
     buffer: VecDeque<O>,
     /// Buffer passed to the user for writing.
     user_buffer: Vec<O>,
+    /// The samples taken from the front of the `buffer` for each write to the backend.
+    write_buffer: Vec<O>,
     /// Number of channels.
     channels: Channels,
-    /// Stream sample rate.
-    sample_hz: SampleHz,
+    /// The exact stream sample rate.
+    sample_hz: f64,
     /// Frames per buffer.
     frames: Frames,
-    /// The port audio stream.
-    stream: pa::Stream<O, O>,
+    /// The number of frames yielded by events or written so far.
+    frames_processed: u64,
+    /// Where diagnostics are sent.
+    diagnostics: Diagnostics,
+    /// Counts the xruns reported while waiting on the stream.
+    xruns: XrunCounter,
+    /// The error that most recently ended iteration.
+    maybe_last_error: Option<Error>,
+    /// The backend's stream.
+    stream: Box<RawStream<O, O>>,
+    /// Keeps the backend initialized while the stream is open.
+    host: Host<B>,
+    /// The parameters negotiated with the device.
+    params: StreamParams<O>,
     is_closed: bool,
-    marker: PhantomData<&'a ()>,
+}
+
+/// A `futures::Sink` of interleaved buffers to be played by a non-blocking output stream's
+/// callback.
+///
+/// Buffers may be of any length. Should the sink fall behind, the callback outputs silence.
+#[cfg(feature="futures")]
+pub struct BufferSink<O> {
+    /// The buffers waiting to be played.
+    queued: Sender<Vec<O>>,
+    /// The buffers that have been played, handed back by the callback.
+    spent: Receiver<Vec<O>>,
+    /// Spent buffers ready to be reused via `empty_buffer`.
+    pool: Vec<Vec<O>>,
+    /// Woken by the callback whenever it takes a buffer from the queue.
+    wakeup: Wakeup,
 }
 
 /// Stream callback function type.
-pub type Callback<O> = Box<FnMut(&mut[O], Settings, DeltaTimeSeconds, CallbackFlags) -> CallbackResult>;
+pub type Callback<O> =
+    Box<FnMut(BufferMut<O>, Settings, StreamTime, CallbackFlags) -> CallbackResult>;
+
+/// Stream callback function type that writes each buffer in planar form.
+pub type PlanarCallback<O> =
+    Box<FnMut(PlanarMut<O>, Settings, StreamTime, CallbackFlags) -> CallbackResult>;
+
+/// Stream callback function type that also receives the messages sent to the stream.
+pub type MessageCallback<O, M> =
+    Box<FnMut(BufferMut<O>, Settings, StreamTime, CallbackFlags, Drain<M>) -> CallbackResult>;
+
+/// Stream callback function type that may also push values out of the audio thread.
+pub type ProducerCallback<O, E> = Box<FnMut(BufferMut<O>, Settings, StreamTime, CallbackFlags,
+                                            &mut Producer<E>) -> CallbackResult>;
 
 /// A handle to the non-blocking output stream.
-pub struct NonBlockingStream<O=Wave> where O: Sample + PaSample {
-    /// The port audio stream.
-    stream: pa::Stream<O, O>,
+pub struct NonBlockingStream<O=Wave, B=PortAudio> where O: Sample + PaSample, B: Backend {
+    /// The backend's stream.
+    stream: Box<RawStream<O, O>>,
+    /// Keeps the backend initialized while the stream is open.
+    host: Host<B>,
+    /// The parameters negotiated with the device.
+    params: StreamParams<O>,
+    /// The settings with which the stream was opened.
+    settings: Settings,
+    /// The number of frames processed by the callback.
+    frames_processed: FramesProcessed,
+    /// Where diagnostics are sent.
+    diagnostics: Diagnostics,
+    /// Counts the xruns reported to the callback.
+    xruns: XrunCounter,
+    /// Marks the stream's async adapter, if any, as closed once the stream is closed.
+    #[cfg(feature="futures")]
+    maybe_notifier: Option<Notifier>,
     /// Is the stream currently closed.
     is_closed: bool,
 }
 
 /// An event returned by the Blocking Stream.
+///
+/// Holds the buffer to be written to, the stream's settings and the index of the buffer's first
+/// frame.
 #[derive(Debug)]
-pub struct Event<'a, O: 'a>(pub &'a mut [O], pub Settings);
+pub struct Event<'a, O: 'a>(pub &'a mut [O], pub Settings, pub u64);
 
-impl<O> Builder<O> where O: Sample + PaSample {
+impl<O, B> Builder<O, B> where O: Sample + PaSample, B: Backend {
 
     /// Retrieve the flags, output stream parameters, sample rate and frames per buffer.
-    fn unwrap_params(self) -> Result<PaParams, Error> {
-        let Builder { stream_params, output_params } = self;
-        let SoundStream { maybe_buffer_frequency, maybe_sample_hz, maybe_flags } = stream_params;
+    fn unwrap_params(&self) -> Result<PaParams, Error> {
+        let Builder { ref stream_params, ref output_params } = *self;
+        let SoundStream {
+            maybe_buffer_frequency, maybe_flags, strict_channels, ref backend, ..
+        } = *stream_params;
+
+        // Check the requested values before querying any devices.
+        stream_params.validate()?;
+        output_params.validate()?;
 
         // Retrieve any stream flags.
-        let flags = maybe_flags.unwrap_or_else(|| StreamFlags::empty());
+        let flags = maybe_flags.unwrap_or_else(StreamFlags::empty);
 
         // Construct the PortAudio output params from the sound stream ones.
         let output_params = {
-            let idx = output_params.idx.unwrap_or_else(|| pa::device::get_default_output());
-            let info = match pa::device::get_info(idx) {
-                Ok(info) => info,
-                Err(err) => return Err(Error::PortAudio(err)),
-            };
-            let channels = output_params.channel_count
-                .map(|n| ::std::cmp::min(n, info.max_output_channels))
-                .unwrap_or_else(|| ::std::cmp::min(2, info.max_output_channels));
+            let idx = output_params.resolve_device(backend, Direction::Output)?;
+            let info = backend.device_info(idx)?;
+            let channels = output_params.negotiate_channels(&info, Direction::Output,
+                                                            strict_channels)?;
             let sample_format = output_params.sample_format();
             let suggested_latency = output_params.suggested_latency
-                .unwrap_or_else(|| info.default_low_output_latency);
+                .unwrap_or(info.default_low_output_latency);
             pa::StreamParameters {
                 device: idx,
                 channel_count: channels,
-                sample_format: sample_format,
-                suggested_latency: suggested_latency,
+                sample_format,
+                suggested_latency,
             }
         };
 
         // Determine the sample rate.
-        let sample_hz = match maybe_sample_hz {
-            Some(sample_hz) => sample_hz,
-            None => match pa::device::get_info(output_params.device) {
-                Ok(info) => info.default_sample_rate,
-                Err(err) => return Err(Error::PortAudio(err)),
-            },
-        };
+        let sample_hz = stream_params.negotiate_sample_hz(None, Some(&output_params))?;
 
         // Determine the closest number of frames per buffer to the requested rate.
         let frames = match maybe_buffer_frequency {
-            Some(BufferFrequency::Frames(frames)) => frames as u32,
+            Some(BufferFrequency::Frames(frames)) => frames,
             Some(BufferFrequency::Hz(hz)) => (sample_hz as f32 / hz).round() as u32,
             None => 0,
         };
@@ -110,221 +192,605 @@ impl<O> Builder<O> where O: Sample + PaSample {
         Ok((flags, output_params, sample_hz, frames))
     }
 
+    /// Check whether the device, channels, sample format and sample rate are supported by the
+    /// backend without starting any audio.
+    ///
+    /// Returns the format that would be requested when the stream is run, or an
+    /// `UnsupportedFormat` error describing it. Note that the number of channels is clamped to
+    /// the device's maximum as it would be when run, unless `strict_channels` was requested.
+    pub fn check(&self) -> Result<StreamFormat, Error> {
+        let backend = &self.stream_params.backend;
+        backend.initialize()?;
+        let result = self.unwrap_params().and_then(|(_, output_params, sample_hz, _)| {
+            check_format(backend, None, Some(&output_params), sample_hz)
+        });
+        backend.terminate()?;
+        result
+    }
+
+    /// Whether or not the stream's format is supported. See `check` for details.
+    pub fn is_supported(&self) -> bool {
+        self.check().is_ok()
+    }
+
     /// Launch a non-blocking output stream with the given callback!
     #[inline]
-    pub fn run_callback(self, mut callback: Callback<O>) -> Result<NonBlockingStream<O>, Error>
+    pub fn run_callback(self, callback: Callback<O>) -> Result<NonBlockingStream<O, B>, Error>
+        where O: 'static,
+    {
+        self.run_callback_with(move |_| callback)
+    }
+
+    /// Launch a non-blocking output stream with the callback returned by `new_callback`.
+    ///
+    /// `new_callback` is given the negotiated settings before the stream is opened, so that any
+    /// buffers used by the callback may be allocated up front.
+    fn run_callback_with<F>(self, new_callback: F) -> Result<NonBlockingStream<O, B>, Error>
         where O: 'static,
+              F: FnOnce(Settings) -> Callback<O>,
     {
 
-        // Initialize PortAudio.
-        try!(pa::initialize().map_err(|err| Error::PortAudio(err)));
+        // Initialize the backend. The `Host` terminates it again should anything below fail.
+        self.stream_params.backend.initialize()?;
+        let params = self.unwrap_params();
+        let host = Host::initialized(self.stream_params.backend);
+        let diagnostics = self.stream_params.diagnostics;
 
-        let (flags, output_params, sample_hz, frames) = try!(self.unwrap_params());
+        let (flags, output_params, sample_hz, frames) = params?;
         let channels = output_params.channel_count;
+        let mut callback =
+            new_callback(Settings::from_exact_sample_hz(sample_hz, frames, channels as u16));
 
-        // Here we construct our PortAudio stream.
-        let mut stream = pa::Stream::new();
+        // The callback learns the actual sample rate once the stream has been opened.
+        let shared_sample_hz = SharedSampleHz::new(sample_hz);
+        let callback_sample_hz = shared_sample_hz.clone();
 
-        // Remember the last time the callback was called so we can create the delta time.
-        let mut maybe_last_time = None; 
+        // Tracks the time and position of each buffer.
+        let mut timer = Timer::new();
+        let frames_processed = timer.frames_processed();
+
+        // Count the xruns reported to the callback.
+        let xruns = XrunCounter::new();
+        let callback_xruns = xruns.clone();
 
         // Construct a wrapper function around our callback.
         let f = Box::new(move |_input: &[O],
                                output: &mut[O],
                                frames: u32,
                                time_info: &pa::StreamCallbackTimeInfo,
-                               flags: pa::StreamCallbackFlags| -> pa::StreamCallbackResult {
-            let settings = Settings {
-                sample_hz: sample_hz as u32,
-                frames: frames as u16,
-                channels: channels as u16,
-            };
-            let dt = time_info.current_time - maybe_last_time.unwrap_or(time_info.current_time);
-            maybe_last_time = Some(time_info.current_time);
-            match callback(output, settings, dt, flags) {
-                CallbackResult::Continue => pa::StreamCallbackResult::Continue,
-                CallbackResult::Complete => pa::StreamCallbackResult::Complete,
-                CallbackResult::Abort    => pa::StreamCallbackResult::Abort,
-            }
+                               flags: CallbackFlags| -> CallbackResult {
+            let sample_hz = callback_sample_hz.get();
+            let settings = Settings::from_exact_sample_hz(sample_hz, frames, channels as u16);
+            let time = timer.next(time_info, frames);
+            callback_xruns.record(flags);
+            callback(BufferMut::new(output, channels as usize), settings, time, flags)
         });
 
         // Here we open the stream.
-        try!(stream.open(None, Some(&output_params), sample_hz, frames, flags, Some(f))
-            .map_err(|err| Error::PortAudio(err)));
+        let (input, output) = (None, Some(&output_params));
+        let result = host.backend().open_stream(input, output, sample_hz, frames, flags, Some(f));
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => return Err(format_error(err, host.backend(), input, output, sample_hz)),
+        };
+
+        let sample_hz = stream.sample_hz();
+        shared_sample_hz.set(sample_hz);
 
         // And now let's kick it off!
-        try!(stream.start().map_err(|err| Error::PortAudio(err)));
+        stream.start()?;
+
+        Ok(NonBlockingStream {
+            stream,
+            host,
+            params: StreamParams::negotiated(&output_params),
+            settings: Settings::from_exact_sample_hz(sample_hz, frames, channels as u16),
+            frames_processed,
+            xruns,
+            diagnostics,
+            #[cfg(feature="futures")]
+            maybe_notifier: None,
+            is_closed: false,
+        })
+    }
+
+    /// Launch a non-blocking output stream with a callback that writes each buffer in planar
+    /// (non-interleaved) form, with each channel's samples stored contiguously.
+    ///
+    /// rust-portaudio only accepts interleaved buffers, so the callback writes to a zeroed buffer
+    /// that is allocated up front and is then interleaved into the output. With a variable number
+    /// of frames per buffer, buffers larger than `MINIMUM_BUFFER_RESERVATION` frames are written
+    /// by the callback in chunks.
+    pub fn run_planar_callback(self, mut callback: PlanarCallback<O>)
+        -> Result<NonBlockingStream<O, B>, Error>
+        where O: 'static,
+    {
+        self.run_callback_with(move |settings| {
+            let max_frames = planar_frames(settings);
+            let mut planar = vec![O::zero(); max_frames * settings.channels as usize];
+            Box::new(move |mut output: BufferMut<O>, settings: Settings, time, flags| {
+                let channels = settings.channels as usize;
+                let mut written = 0;
+                let result = for_each_chunk(settings, time, max_frames, |frames, settings, time| {
+                    let planar = &mut planar[..frames.len() * channels];
+                    for sample in planar.iter_mut() {
+                        *sample = O::zero();
+                    }
+                    let result = callback(PlanarMut::new(planar, channels), settings, time, flags);
+                    interleave(planar, &mut output[frames.start * channels..frames.end * channels],
+                               channels);
+                    written = frames.end * channels;
+                    result
+                });
+                // Silence whatever follows the chunk at which the callback stopped.
+                for sample in output[written..].iter_mut() {
+                    *sample = O::zero();
+                }
+                result
+            })
+        })
+    }
+
+    /// Launch a non-blocking output stream with the given callback along with a `Sender` for
+    /// passing messages to it.
+    ///
+    /// All messages that are pending at the start of each buffer are yielded by the `Drain`
+    /// given to the callback. The channel can hold up to `capacity` messages at once and is
+    /// wait-free on both ends, so no locks are ever taken on the audio thread.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    pub fn run_callback_with_messages<M>(self, capacity: usize, mut callback: MessageCallback<O, M>)
+        -> Result<(NonBlockingStream<O, B>, Sender<M>), Error>
+        where O: 'static,
+              M: Send + 'static,
+    {
+        let (sender, mut receiver) = spsc::channel(capacity);
+        let f = Box::new(move |output: BufferMut<O>, settings: Settings, time, flags| {
+            callback(output, settings, time, flags, receiver.drain())
+        });
+        self.run_callback(f).map(|stream| (stream, sender))
+    }
 
-        Ok(NonBlockingStream { stream: stream, is_closed: false })
+    /// Launch a non-blocking output stream with the given callback along with a `Consumer` for
+    /// receiving the values that it pushes to its `Producer`.
+    ///
+    /// The channel can hold up to `capacity` values at once. Values pushed while it is full are
+    /// counted by `Consumer::overflows` rather than blocking the audio thread.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    pub fn run_callback_with_producer<E>(self,
+                                         capacity: usize,
+                                         mut callback: ProducerCallback<O, E>)
+        -> Result<(NonBlockingStream<O, B>, Consumer<E>), Error>
+        where O: 'static,
+              E: Send + 'static,
+    {
+        let (mut producer, consumer) = spsc::counting_channel(capacity);
+        let f = Box::new(move |output: BufferMut<O>, settings: Settings, time, flags| {
+            callback(output, settings, time, flags, &mut producer)
+        });
+        self.run_callback(f).map(|stream| (stream, consumer))
+    }
+
+    /// Launch a non-blocking output stream along with a `futures::Sink` of buffers for it to play.
+    ///
+    /// Up to `capacity` buffers may be queued at once, after which the sink applies backpressure
+    /// until the callback takes the next buffer and wakes the sending task.
+    ///
+    /// **Panics** if `capacity` is `0`.
+    #[cfg(feature="futures")]
+    pub fn run_sink(self, capacity: usize)
+        -> Result<(NonBlockingStream<O, B>, BufferSink<O>), Error>
+        where O: Send + 'static,
+    {
+        let (queued, mut queued_receiver) = spsc::channel::<Vec<O>>(capacity);
+        // Room for every queued buffer along with the one being played, so that the callback
+        // never has to free a buffer itself.
+        let (mut spent_sender, spent) = spsc::channel(capacity + 1);
+        let (notifier, wakeup) = wakeup();
+        let closer = wakeup.notifier();
+        let mut current: Option<Vec<O>> = None;
+        let mut position = 0;
+        let f = Box::new(move |mut output: BufferMut<O>, _settings: Settings, _time, _flags| {
+            let mut written = 0;
+            while written < output.len() {
+                let buffer = match current.take().or_else(|| queued_receiver.try_recv()) {
+                    Some(buffer) => buffer,
+                    None => break,
+                };
+                if position == 0 {
+                    notifier.notify();
+                }
+                let len = ::std::cmp::min(output.len() - written, buffer.len() - position);
+                output[written..written + len].copy_from_slice(&buffer[position..position + len]);
+                written += len;
+                position += len;
+                if position == buffer.len() {
+                    position = 0;
+                    let _ = spent_sender.try_send(buffer);
+                } else {
+                    current = Some(buffer);
+                }
+            }
+            // Fill the remainder with silence should the sink have fallen behind.
+            for sample in output[written..].iter_mut() {
+                *sample = O::zero();
+            }
+            CallbackResult::Continue
+        });
+        let sink = BufferSink { queued, spent, pool: Vec::new(), wakeup };
+        self.run_callback(f).map(|mut stream| {
+            stream.maybe_notifier = Some(closer);
+            (stream, sink)
+        })
     }
 
     /// Launch a blocking output stream!
     #[inline]
-    pub fn run<'a>(self) -> Result<BlockingStream<'a, O>, Error>
+    pub fn run(self) -> Result<BlockingStream<O, B>, Error>
         where O: 'static,
     {
 
-        // Initialize PortAudio.
-        try!(pa::initialize().map_err(|err| Error::PortAudio(err)));
+        // Initialize the backend. The `Host` terminates it again should anything below fail.
+        self.stream_params.backend.initialize()?;
+        let params = self.unwrap_params();
+        let host = Host::initialized(self.stream_params.backend);
+        let diagnostics = self.stream_params.diagnostics;
 
-        let (flags, output_params, sample_hz, frames) = try!(self.unwrap_params());
-
-        // Here we construct our PortAudio stream.
-        let mut stream = pa::Stream::new();
+        let (flags, output_params, sample_hz, frames) = params?;
 
         // Here we open the stream.
-        try!(stream.open(None, Some(&output_params), sample_hz, frames, flags, None)
-            .map_err(|err| Error::PortAudio(err)));
+        let (input, output) = (None, Some(&output_params));
+        let result = host.backend()
+            .open_stream::<O, O>(input, output, sample_hz, frames, flags, None);
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) => return Err(format_error(err, host.backend(), input, output, sample_hz)),
+        };
+
+        let sample_hz = stream.sample_hz();
 
         // And now let's kick it off!
-        try!(stream.start().map_err(|err| Error::PortAudio(err)));
+        stream.start()?;
 
         let channels = output_params.channel_count;
         let double_buffer_len = (frames as usize * channels as usize) * 2;
         let buffer_len = ::std::cmp::max(double_buffer_len, MINIMUM_BUFFER_RESERVATION);
 
+        let buffer = VecDeque::with_capacity(buffer_len);
+        // With a variable number of frames per buffer, the user may fill the whole queue.
+        let user_buffer = Vec::with_capacity(buffer.capacity());
+        let write_buffer = Vec::with_capacity(buffer.capacity());
+
         Ok(BlockingStream {
-            buffer: VecDeque::with_capacity(buffer_len),
-            user_buffer: Vec::with_capacity(frames as usize * channels as usize),
-            stream: stream,
+            buffer,
+            user_buffer,
+            write_buffer,
+            stream,
+            host,
+            params: StreamParams::negotiated(&output_params),
             channels: channels as u16,
-            frames: frames as u16,
-            sample_hz: sample_hz as u32,
+            frames,
+            sample_hz,
+            frames_processed: 0,
+            xruns: XrunCounter::new(),
+            maybe_last_error: None,
+            diagnostics,
             is_closed: false,
-            marker: PhantomData,
         })
     }
 
 }
 
-impl<O> NonBlockingStream<O> where O: Sample + PaSample {
+impl<O, B> NonBlockingStream<O, B> where O: Sample + PaSample, B: Backend {
 
-    /// Close the stream and terminate PortAudio.
+    /// Close the stream, terminating the backend if no other stream or `Host` is using it.
     pub fn close(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
         self.is_closed = true;
-        try!(self.stream.close().map_err(|err| Error::PortAudio(err)));
-        try!(pa::terminate().map_err(|err| Error::PortAudio(err)));
+        // The backend may keep the callback and its `Notifier` alive until the stream is
+        // dropped, so end any async adapter now.
+        #[cfg(feature="futures")]
+        let _ = self.maybe_notifier.take();
+        self.stream.close()?;
+        self.host.release()?;
         Ok(())
     }
 
+    /// The parameters negotiated with the device, including the actual number of channels.
+    pub fn params(&self) -> &StreamParams<O> {
+        &self.params
+    }
+
+    /// The settings with which the stream was opened, including the negotiated sample rate.
+    ///
+    /// If a variable number of frames per buffer was requested, `frames` will be `0`.
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// The number of frames processed by the callback since the stream was started.
+    ///
+    /// Unlike summing each `dt`, this count is exact and may be used for sample-accurate timing.
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed.get()
+    }
+
+    /// The number of each kind of under or overflow reported to the callback so far.
+    pub fn xrun_stats(&self) -> XrunStats {
+        self.xruns.stats()
+    }
+
     /// Check whether or not the stream is currently active.
     pub fn is_active(&self) -> Result<bool, Error> {
-        self.stream.is_active().map_err(|err| Error::PortAudio(err))
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.is_active()
+    }
+
+    /// Stop the stream once all pending buffers have been played, keeping the callback and the
+    /// device configuration so that it may later be resumed with `start`.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.stop()
+    }
+
+    /// Stop the stream immediately, discarding any pending buffers. The stream may later be
+    /// resumed with `start`.
+    pub fn abort(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.abort()
+    }
+
+    /// Resume a stream that was previously stopped or aborted.
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.start()
+    }
+
+    /// Check whether or not the stream is currently stopped.
+    pub fn is_stopped(&self) -> Result<bool, Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
+        self.stream.is_stopped()
     }
 
 }
 
-impl<O> Drop for NonBlockingStream<O> where O: Sample + PaSample {
+impl<O, B> Drop for NonBlockingStream<O, B> where O: Sample + PaSample, B: Backend {
     fn drop(&mut self) {
         if !self.is_closed {
             if let Err(err) = self.close() {
-                println!("An error occurred while closing NonBlockingStream: {}", err);
+                self.diagnostics.emit(Diagnostic::CloseFailed(err));
             }
         }
     }
 }
 
-impl<'a, O> BlockingStream<'a, O> where O: Sample + PaSample {
-    /// Close the stream and terminate PortAudio.
+impl<O, B> BlockingStream<O, B> where O: Sample + PaSample, B: Backend {
+    /// Close the stream, terminating the backend if no other stream or `Host` is using it.
     pub fn close(&mut self) -> Result<(), Error> {
+        if self.is_closed {
+            return Err(Error::StreamClosed);
+        }
         self.is_closed = true;
-        try!(self.stream.close().map_err(|err| Error::PortAudio(err)));
-        try!(pa::terminate().map_err(|err| Error::PortAudio(err)));
+        self.stream.close()?;
+        self.host.release()?;
         Ok(())
     }
+
+    /// The parameters negotiated with the device, including the actual number of channels.
+    pub fn params(&self) -> &StreamParams<O> {
+        &self.params
+    }
+
+    /// The settings with which the stream was opened, including the negotiated sample rate.
+    ///
+    /// If a variable number of frames per buffer was requested, `frames` will be `0` and the
+    /// settings of each event hold the number of frames that it contains.
+    pub fn settings(&self) -> Settings {
+        Settings::from_exact_sample_hz(self.sample_hz, self.frames, self.channels)
+    }
+
+    /// The number of frames yielded by the stream's events or written via `write_frames` so far.
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed
+    }
+
+    /// The number of each kind of under or overflow that has occurred so far.
+    pub fn xrun_stats(&self) -> XrunStats {
+        self.xruns.stats()
+    }
+
+    /// Write interleaved frames from the given buffer, blocking until at least one frame may be
+    /// written.
+    ///
+    /// Writes as many whole frames as the stream can currently accept, returning the number of
+    /// frames written. Any frames still queued from `next_buffer` are written first. Returns `0`
+    /// only if the buffer is too short to hold a single frame.
+    ///
+    /// While the stream reports that no frames may be written, this spins, polling it again
+    /// without sleeping.
+    pub fn write_frames(&mut self, buffer: &[O]) -> Result<usize, Error> {
+        let channels = self.channels as usize;
+        let max_frames = buffer.len() / channels;
+        if max_frames == 0 {
+            return Ok(0);
+        }
+
+        let BlockingStream {
+            buffer: ref mut queue,
+            ref mut user_buffer,
+            ref mut write_buffer,
+            ref mut stream,
+            ref xruns,
+            ref diagnostics,
+            ref mut frames_processed,
+            ..
+        } = *self;
+
+        // Queue any frames written to the last buffer given by `next_buffer`.
+        if !user_buffer.is_empty() {
+            queue.extend(user_buffer.iter().cloned());
+            user_buffer.clear();
+        }
+
+        loop {
+            let available = wait_for_stream(|| stream.write_available(), xruns, diagnostics)?;
+            if available == 0 {
+                continue;
+            }
+
+            // Write the queued frames first so that they are played in order.
+            if queue.len() >= channels {
+                let queued_frames = (queue.len() / channels) as u32;
+                let write_frames = ::std::cmp::min(available, queued_frames);
+                write_buffer.resize(write_frames as usize * channels, O::zero());
+                drain_front(queue, &mut write_buffer[..]);
+                stream.write(&write_buffer[..], write_frames)?;
+                continue;
+            }
+
+            let frames = ::std::cmp::min(available as usize, max_frames);
+            stream.write(&buffer[..frames * channels], frames as u32)?;
+            *frames_processed += frames as u64;
+            return Ok(frames);
+        }
+    }
+
 }
 
-impl<'a, O> Drop for BlockingStream<'a, O> where O: Sample + PaSample {
+impl<O, B> Drop for BlockingStream<O, B> where O: Sample + PaSample, B: Backend {
     fn drop(&mut self) {
         if !self.is_closed {
             if let Err(err) = self.close() {
-                println!("An error occurred while closing BlockingStream: {}", err);
+                self.diagnostics.emit(Diagnostic::CloseFailed(err));
             }
         }
     }
 }
 
-impl<'a, O> Iterator for BlockingStream<'a, O>
-    where O: Sample + PaSample + 'a
-{
-    type Item = Event<'a, O>;
+impl<O, B> BlockingStream<O, B> where O: Sample + PaSample, B: Backend {
+
+    /// Wait for the next buffer to be written to.
+    ///
+    /// Returns `None` should writing to the stream fail, in which case the error is sent to the
+    /// stream's diagnostics sink and may be retrieved via `last_error`.
+    pub fn next_buffer<'a>(&'a mut self) -> Option<Event<'a, O>> {
+        match self.next_event() {
+            Ok((settings, frame)) => Some(Event(&mut self.user_buffer[..], settings, frame)),
+            Err((operation, err)) => {
+                self.diagnostics.emit(Diagnostic::BlockingStreamEnded(operation, err.clone()));
+                self.maybe_last_error = Some(err);
+                None
+            },
+        }
+    }
+
+    /// Wait for the next buffer to be written to, returning the error should writing to the
+    /// stream fail.
+    ///
+    /// Unlike `next_buffer`, errors are returned rather than being sent to the stream's
+    /// diagnostics sink. The stream may be retried after an error.
+    pub fn try_next<'a>(&'a mut self) -> Result<Event<'a, O>, Error> {
+        let (settings, frame) = self.next_event().map_err(|(_, err)| err)?;
+        Ok(Event(&mut self.user_buffer[..], settings, frame))
+    }
+
+    /// Call the given function with each buffer to be written to until it returns anything other
+    /// than `CallbackResult::Continue`, or until writing to the stream fails.
+    pub fn for_each_buffer<F>(&mut self, mut f: F) -> Result<(), Error>
+        where F: FnMut(Event<O>) -> CallbackResult,
+    {
+        loop {
+            let event = self.try_next()?;
+            if f(event) != CallbackResult::Continue {
+                return Ok(());
+            }
+        }
+    }
 
-    fn next(&mut self) -> Option<Event<'a, O>> {
-        use std::error::Error as StdError;
-        use utils::take_front;
+    /// The error that most recently caused `next_buffer` to return `None`, if any.
+    pub fn last_error(&self) -> Option<&Error> {
+        self.maybe_last_error.as_ref()
+    }
 
+    /// Wait until the stream is ready for the next buffer, returning its settings and the index of
+    /// its first frame, or the failed operation along with its error.
+    ///
+    /// The buffer itself is left zeroed in the `user_buffer`. With a variable number of frames per
+    /// buffer, each buffer is as long as the stream can currently accept.
+    fn next_event(&mut self) -> Result<(Settings, u64), (StreamOperation, Error)> {
         let BlockingStream {
             ref mut buffer,
             ref mut user_buffer,
+            ref mut write_buffer,
             ref mut stream,
+            ref xruns,
+            ref diagnostics,
             ref channels,
             ref frames,
             ref sample_hz,
+            ref mut frames_processed,
             ..
         } = *self;
 
-        let settings = Settings { channels: *channels, frames: *frames, sample_hz: *sample_hz };
-        let buffer_size = settings.buffer_size();
-
-        if user_buffer.len() > 0 {
-            buffer.extend(user_buffer.iter().map(|&sample| sample));
+        if !user_buffer.is_empty() {
+            buffer.extend(user_buffer.iter().cloned());
             user_buffer.clear();
         }
 
         loop {
 
             // How many frames are available for writing on the output stream?
-            let available_frames = match wait_for_stream(|| stream.get_stream_write_available()) {
+            let available_frames = wait_for_stream(|| stream.write_available(), xruns, diagnostics);
+            let available_frames = match available_frames {
                 Ok(frames) => frames,
-                Err(err) => {
-                    println!("An error occurred while requesting the number of available \
-                             frames for writing from the output stream: {}. BlockingStream will \
-                             now exit the event loop.", StdError::description(&err));
-                    return None;
-                },
+                Err(err) => return Err((StreamOperation::WriteAvailable, err)),
             };
 
             // How many frames do we have in our output_buffer so far?
             let output_buffer_frames = (buffer.len() / *channels as usize) as u32;
 
             // If there are frames available for writing and we have some to write, then write!
+            let mut write_frames = 0;
             if available_frames > 0 && output_buffer_frames > 0 {
-                // If we have more than enough frames for writing, take them from the start of the buffer.
-                let (write_buffer, write_frames) = if output_buffer_frames >= available_frames {
-                    let out_samples = (available_frames * *channels as u32) as usize;
-                    let write_buffer = take_front(buffer, out_samples);
-                    (write_buffer, available_frames)
-                }
-                // Otherwise if we have less, just take what we can for now.
-                else {
-                    let len = buffer.len();
-                    let write_buffer = take_front(buffer, len);
-                    (write_buffer, output_buffer_frames)
-                };
-                if let Err(err) = stream.write(write_buffer, write_frames) {
-                    println!("An error occurred while writing to the output stream: {}. \
-                             BlockingStream will now exit the event loop.",
-                             StdError::description(&err));
-                    return None
+                // Take as many frames as may be written from the start of the buffer.
+                write_frames = ::std::cmp::min(available_frames, output_buffer_frames);
+                write_buffer.resize((write_frames * *channels as u32) as usize, O::zero());
+                drain_front(buffer, &mut write_buffer[..]);
+                if let Err(err) = stream.write(&write_buffer[..], write_frames) {
+                    return Err((StreamOperation::Write, err));
                 }
             }
 
+            // Ask for the requested number of frames, or for as many as the stream can currently
+            // take if the number may vary.
+            let room_frames = ((buffer.capacity() - buffer.len()) / *channels as usize) as u32;
+            let event_frames = match *frames {
+                0 => ::std::cmp::min(available_frames - write_frames, room_frames),
+                frames => frames,
+            };
+
             // If we need more frames, return a buffer for writing.
-            if buffer.len() <= buffer.capacity() - buffer_size {
+            if event_frames > 0 && event_frames <= room_frames {
                 use std::iter::repeat;
-                // Start the slice just after the already filled samples.
-                let start = user_buffer.len();
+                let settings = Settings::from_exact_sample_hz(*sample_hz, event_frames, *channels);
                 // Extend the update buffer by the necessary number of frames.
-                user_buffer.extend(repeat(O::zero()).take(buffer_size));
-                // Here we obtain a mutable reference to the slice with the correct lifetime so
-                // that we can return it via our `Event::Out`. Note: This means that a twisted,
-                // evil person could do horrific things with this iterator by calling `.next()`
-                // multiple times and storing aliasing mutable references to our output buffer,
-                // HOWEVER - this is extremely unlikely to occur in practise as the api is designed
-                // in a way that the reference is intended to die at the end of each loop before
-                // `.next()` even gets called again.
-                let slice = unsafe { ::std::mem::transmute(&mut user_buffer[start..]) };
-                return Some(Event(slice, settings));
+                user_buffer.extend(repeat(O::zero()).take(settings.buffer_size()));
+                let frame = *frames_processed;
+                *frames_processed += settings.frames as u64;
+                return Ok((settings, frame));
             }
 
         }
@@ -333,4 +799,123 @@ impl<'a, O> Iterator for BlockingStream<'a, O>
 
 }
 
+#[cfg(feature="futures")]
+impl<O> BufferSink<O> {
+
+    /// An empty buffer to fill and send, reusing one that has already been played if possible.
+    pub fn empty_buffer(&mut self) -> Vec<O> {
+        self.collect_spent();
+        match self.pool.pop() {
+            Some(mut buffer) => {
+                buffer.clear();
+                buffer
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Move the buffers handed back by the callback into the `pool`.
+    fn collect_spent(&mut self) {
+        while let Some(buffer) = self.spent.try_recv() {
+            self.pool.push(buffer);
+        }
+    }
+
+}
+
+// The sink never pins its buffers, so it may be moved whatever the sample type.
+#[cfg(feature="futures")]
+impl<O> Unpin for BufferSink<O> {}
+
+#[cfg(feature="futures")]
+impl<O> Sink<Vec<O>> for BufferSink<O> {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        self.collect_spent();
+        // Register before checking the queue so that a slot freed in between still wakes us.
+        self.wakeup.register(cx.waker());
+        if self.wakeup.is_closed() {
+            return Poll::Ready(Err(Error::StreamClosed));
+        }
+        if self.queued.is_full() {
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(()))
+    }
 
+    /// **Panics** if `poll_ready` has not first reported that there is room for the buffer.
+    fn start_send(mut self: Pin<&mut Self>, buffer: Vec<O>) -> Result<(), Error> {
+        if self.wakeup.is_closed() {
+            return Err(Error::StreamClosed);
+        }
+        if self.queued.try_send(buffer).is_err() {
+            panic!("`start_send` was called before `poll_ready` reported room for a buffer");
+        }
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        self.collect_spent();
+        self.wakeup.register(cx.waker());
+        if self.queued.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        if self.wakeup.is_closed() {
+            return Poll::Ready(Err(Error::StreamClosed));
+        }
+        Poll::Pending
+    }
+
+    /// Wait for the queued buffers to be played. The stream itself keeps running until closed.
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(all(test, feature="futures"))]
+mod tests {
+    use error::Error;
+    use futures_sink::Sink;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use {Offline, SoundStream, StreamParams};
+
+    struct NoWake;
+
+    impl Wake for NoWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[test]
+    fn buffer_sink_ends_once_closed() {
+        let offline = Offline::new().channels(0, 1);
+        let (mut stream, sink) = SoundStream::new()
+            .backend(offline.clone())
+            .frames_per_buffer(2)
+            .output(StreamParams::<f32>::new())
+            .run_sink(2)
+            .unwrap();
+        let waker = Waker::from(Arc::new(NoWake));
+        let mut cx = Context::from_waker(&waker);
+        let mut sink = sink;
+
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+        Pin::new(&mut sink).start_send(vec![1.0, 2.0]).unwrap();
+        stream.close().unwrap();
+
+        // The queued buffer will never be played, so flushing fails rather than waiting forever.
+        match Pin::new(&mut sink).poll_flush(&mut cx) {
+            Poll::Ready(Err(Error::StreamClosed)) => (),
+            Poll::Pending => panic!("the sink should end once closed"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match Pin::new(&mut sink).poll_ready(&mut cx) {
+            Poll::Ready(Err(Error::StreamClosed)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(Pin::new(&mut sink).start_send(vec![3.0, 4.0]).is_err());
+    }
+
+}
@@ -1,10 +1,43 @@
 
 use std::collections::VecDeque;
 
-/// Take the given number of elements from the front of the VecDeque.
+/// Move elements from the front of the VecDeque into the given slice until it is full.
 ///
-/// Fails if the num_elems given is higher than the length of the VecDeque.
-pub fn take_front<T>(vec: &mut VecDeque<T>, num_elems: usize) -> Vec<T> {
-    (0..num_elems).map(|_| vec.pop_front().unwrap()).collect()
+/// Copies whole slices rather than popping each element, and never allocates.
+///
+/// Fails if the slice is longer than the VecDeque.
+pub fn drain_front<T>(deque: &mut VecDeque<T>, slice: &mut [T]) where T: Copy {
+    let len = slice.len();
+    {
+        let (front, back) = deque.as_slices();
+        let front_len = ::std::cmp::min(len, front.len());
+        slice[..front_len].copy_from_slice(&front[..front_len]);
+        slice[front_len..].copy_from_slice(&back[..len - front_len]);
+    }
+    deque.drain(..len);
+}
+
+/// Copy the interleaved samples into `planar`, grouping them by channel so that each channel's
+/// samples are contiguous.
+///
+/// Both slices must be the same length.
+pub fn deinterleave<T>(interleaved: &[T], planar: &mut [T], channels: usize) where T: Copy {
+    let frames = interleaved.len() / channels;
+    for (i, frame) in interleaved.chunks(channels).enumerate() {
+        for (channel, &sample) in frame.iter().enumerate() {
+            planar[channel * frames + i] = sample;
+        }
+    }
 }
 
+/// Copy the planar samples into `interleaved`, the inverse of `deinterleave`.
+///
+/// Both slices must be the same length.
+pub fn interleave<T>(planar: &[T], interleaved: &mut [T], channels: usize) where T: Copy {
+    let frames = planar.len() / channels;
+    for (i, frame) in interleaved.chunks_mut(channels).enumerate() {
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            *sample = planar[channel * frames + i];
+        }
+    }
+}